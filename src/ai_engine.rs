@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::thread::{self, JoinHandle};
+
+use chess::{Player, Square};
+
+use crate::chess_engine::*;
+use crate::local_engine::{LocalGame, Rules};
+
+/// Deepest search allowed, every extra move multiplies the time by about 30
+pub const MAX_AI_DEPTH: u32 = 3;
+
+/// Worth more than all the material on the board
+const MATE_SCORE: i32 = 1000;
+
+/// A move as it was played, with the piece it promoted to
+type Played = (ChessMove, Option<&'static str>);
+
+fn piece_value(name: &str) -> i32 {
+    return match name {
+        "P" => 1,
+        "N" | "B" => 3,
+        "R" => 5,
+        "Q" => 9,
+        _ => 0,
+    };
+}
+
+/**
+ * The material of the player to move minus the opponent's, in pawns.
+ */
+fn material(engine: &LocalGame) -> i32 {
+    let player = if engine.get_player() {
+        Player::White
+    } else {
+        Player::Black
+    };
+
+    let mut score = 0;
+    for square in engine.get_board() {
+        if let Square::Occupied(piece) = square {
+            let value = piece_value(piece.kind.name);
+            score += if piece.is_player(player) { value } else { -value };
+        }
+    }
+    return score;
+}
+
+/**
+ * The computer's search for its next move, looking `depth` moves ahead from
+ * the game after `played`. It replays the game on fresh engines as
+ * [LocalGame] cannot be copied, which is slow, so it runs on a thread of its
+ * own.
+ */
+struct Search {
+    rules: Rules,
+    played: Vec<Played>,
+    depth: u32,
+}
+
+impl Search {
+    /**
+     * The game after the moves played so far and then `line`.
+     */
+    fn replay(&self, line: &[Played]) -> LocalGame {
+        let mut engine = LocalGame::with_rules(self.rules);
+        for (mv, promotion) in self.played.iter().chain(line) {
+            engine.apply_promotion(mv, *promotion);
+        }
+        return engine;
+    }
+
+    /**
+     * Score the game after `line` for the player to move, looking `depth`
+     * more moves ahead.
+     */
+    fn score(&self, line: &mut Vec<Played>, depth: u32) -> i32 {
+        let mut engine = self.replay(line);
+        match engine.get_state() {
+            ChessState::Ongoing => (),
+            ChessState::JoeverDraw | ChessState::JoeverIndeterminate
+                => return 0,
+            /* the player to move lost, sooner is worse */
+            _ => return -MATE_SCORE - depth as i32,
+        }
+        if depth == 0 {
+            return material(&engine);
+        }
+
+        let mut best = i32::MIN;
+        for played in engine.get_all_promotions() {
+            line.push(played);
+            best = best.max(-self.score(line, depth - 1));
+            line.pop();
+        }
+        return best;
+    }
+
+    /**
+     * Return the best move for the player to move, if there is any.
+     */
+    fn best_move(&self) -> Option<Played> {
+        let mut best = None;
+        let mut best_score = i32::MIN;
+        for played in self.replay(&[]).get_all_promotions() {
+            let mut line = vec![played];
+            let score = -self.score(&mut line, self.depth - 1);
+            if score > best_score {
+                best_score = score;
+                best = line.pop();
+            }
+        }
+        return best;
+    }
+}
+
+/**
+ * A local game against the computer. The computer looks `depth` moves ahead
+ * and plays for material, taking the first of equally good moves.
+ */
+pub struct AiGame {
+    engine: LocalGame,
+    rules: Rules,
+    /// Everything played so far, for the [Search]
+    played: Vec<Played>,
+    human_white: bool,
+    depth: u32,
+    /// The computer's search while it runs
+    thinking: Option<JoinHandle<Option<Played>>>,
+}
+
+impl AiGame {
+    /**
+     * Start a game where the user plays white if `human_white`, and the
+     * computer searches `depth` moves, from 1 to [MAX_AI_DEPTH].
+     */
+    pub fn new(human_white: bool, depth: u32) -> Self {
+        let rules = Rules::default();
+        return AiGame {
+            engine: LocalGame::with_rules(rules),
+            rules,
+            played: Vec::new(),
+            human_white,
+            depth: depth.clamp(1, MAX_AI_DEPTH),
+            thinking: None,
+        };
+    }
+
+    fn play(&mut self, mv: &ChessMove, promotion: Option<&'static str>)
+        -> bool {
+        if !self.engine.apply_promotion(mv, promotion) {
+            return false;
+        }
+        self.played.push((*mv, promotion));
+        return true;
+    }
+}
+
+impl ChessGame for AiGame {
+    fn get_moves(&mut self, loc: &ChessLoc) -> HashMap<ChessLoc, ChessMove> {
+        return self.engine.get_moves(loc);
+    }
+
+    fn apply_move(&mut self, mv: &ChessMove) -> bool {
+        /* the board only offers promotions to a queen */
+        return self.play(mv, None) || self.play(mv, Some("Q"));
+    }
+
+    fn wait_move(&mut self) -> bool {
+        if self.engine.get_state() != ChessState::Ongoing
+            || self.engine.get_player() == self.human_white {
+            return false;
+        }
+
+        let thinking = self.thinking.take().unwrap_or_else(|| {
+            let search = Search {
+                rules: self.rules,
+                played: self.played.clone(),
+                depth: self.depth,
+            };
+            thread::spawn(move || search.best_move())
+        });
+        if !thinking.is_finished() {
+            self.thinking = Some(thinking);
+            return false;
+        }
+
+        return match thinking.join() {
+            Ok(Some((mv, promotion))) => self.play(&mv, promotion),
+            _ => false,
+        };
+    }
+
+    fn opponent_starts(&self) -> bool {
+        return !self.human_white;
+    }
+
+    fn rules(&self) -> Vec<String> {
+        let mut rules = self.engine.rules();
+        rules.push(format!("The computer looks {} moves ahead", self.depth));
+        return rules;
+    }
+
+    fn get_piece(&mut self, loc: &ChessLoc) -> (bool, String) {
+        return self.engine.get_piece(loc);
+    }
+
+    fn get_player(&self) -> bool {
+        return self.engine.get_player();
+    }
+
+    fn get_state(&mut self) -> ChessState {
+        return self.engine.get_state();
+    }
+}
@@ -0,0 +1,172 @@
+use crate::chess_engine::*;
+
+/**
+ * Copy of every square on the board, indexed by `8*rank + file`. Each entry is
+ * what [ChessGame::get_piece] returned for that square.
+ */
+pub type BoardSnapshot = Vec<(bool, String)>;
+
+/**
+ * Read the whole board of `game` into a [BoardSnapshot].
+ */
+pub fn snapshot(game: &mut dyn ChessGame) -> BoardSnapshot {
+    let mut out = Vec::with_capacity(8*8);
+    for y in 0..8 {
+        for x in 0..8 {
+            out.push(game.get_piece(&(x, y)));
+        }
+    }
+    return out;
+}
+
+fn is_empty(square: &(bool, String)) -> bool {
+    return square.1.trim().is_empty();
+}
+
+fn index_to_loc(i: usize) -> ChessLoc {
+    return ((i % 8) as i32, (i / 8) as i32);
+}
+
+/**
+ * A piece moving from one square to another.
+ */
+pub struct Slide {
+    pub from: ChessLoc,
+    pub to: ChessLoc,
+    pub white: bool,
+    pub piece: String,
+}
+
+/**
+ * A piece disappearing from a square, i.e. a captured piece.
+ */
+pub struct Fade {
+    pub loc: ChessLoc,
+    pub white: bool,
+    pub piece: String,
+}
+
+/**
+ * Work out which pieces moved between `before` and `after`. Castling results
+ * in two slides, en passant and regular captures in a fade.
+ */
+pub fn diff(before: &BoardSnapshot, after: &BoardSnapshot)
+    -> (Vec<Slide>, Vec<Fade>) {
+    let mut vacated: Vec<usize> = Vec::new();
+    let mut arrived: Vec<usize> = Vec::new();
+    for i in 0..before.len().min(after.len()) {
+        if before[i] == after[i] {
+            continue;
+        }
+        if !is_empty(&before[i]) {
+            vacated.push(i);
+        }
+        if !is_empty(&after[i]) {
+            arrived.push(i);
+        }
+    }
+
+    let mut slides = Vec::new();
+    let mut fades = Vec::new();
+    for &to in arrived.iter() {
+        let white = after[to].0;
+        let name = &after[to].1;
+
+        /* prefer the same piece, fall back to a pawn for promotions */
+        let from = vacated.iter().position(|&i| {
+            before[i].0 == white && before[i].1 == *name && i != to
+        }).or_else(|| vacated.iter().position(|&i| {
+            before[i].0 == white && before[i].1 == "P" && i != to
+        }));
+
+        if let Some(j) = from {
+            let from = vacated.remove(j);
+            slides.push(Slide {
+                from: index_to_loc(from),
+                to: index_to_loc(to),
+                white,
+                piece: before[from].1.clone(),
+            });
+        }
+
+        /* whatever stood on the target square got captured */
+        if !is_empty(&before[to]) {
+            vacated.retain(|&i| i != to);
+            fades.push(Fade {
+                loc: index_to_loc(to),
+                white: before[to].0,
+                piece: before[to].1.clone(),
+            });
+        }
+    }
+
+    /* left without a replacement, e.g. an en passant capture */
+    for &i in vacated.iter() {
+        fades.push(Fade {
+            loc: index_to_loc(i),
+            white: before[i].0,
+            piece: before[i].1.clone(),
+        });
+    }
+
+    return (slides, fades);
+}
+
+pub struct Animation {
+    pub slides: Vec<Slide>,
+    pub fades: Vec<Fade>,
+    elapsed: f32,
+    duration: f32,
+}
+
+impl Animation {
+    /**
+     * Animate the changes between `before` and `after`, see [diff]. Returns
+     * [None] if nothing changed or `duration` is not positive.
+     */
+    pub fn between(
+        before: &BoardSnapshot,
+        after: &BoardSnapshot,
+        duration: f32,
+    ) -> Option<Self> {
+        if duration <= 0. {
+            return None;
+        }
+
+        let (slides, fades) = diff(before, after);
+        if slides.is_empty() && fades.is_empty() {
+            return None;
+        }
+
+        return Some(Animation {
+            slides,
+            fades,
+            elapsed: 0.,
+            duration,
+        });
+    }
+
+    /**
+     * Advance the animation by `dt` seconds. Returns [true] once it is done.
+     */
+    pub fn update(&mut self, dt: f32) -> bool {
+        self.elapsed += dt;
+        return self.elapsed >= self.duration;
+    }
+
+    /**
+     * How far along the animation is, eased and clamped to `0..=1`.
+     */
+    pub fn progress(&self) -> f32 {
+        let t = (self.elapsed / self.duration).clamp(0., 1.);
+        return t * (2. - t);
+    }
+
+    /**
+     * Return [true] if a piece is currently sliding onto `loc`, in which case
+     * it should not be drawn at its final square yet.
+     */
+    pub fn is_arriving(&self, loc: &ChessLoc) -> bool {
+        return self.slides.iter().any(|s| s.to == *loc);
+    }
+}
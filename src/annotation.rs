@@ -0,0 +1,146 @@
+use ggez::input::keyboard::KeyMods;
+
+use crate::chess_engine::*;
+use crate::protocol::loc_name;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AnnotationColor {
+    Green,
+    Red,
+    Blue,
+    Yellow,
+}
+
+impl AnnotationColor {
+    /**
+     * Pick the color from the held modifier keys the same way Lichess does:
+     * shift for red, alt for blue and both (or ctrl) for yellow.
+     */
+    pub fn from_mods(mods: KeyMods) -> Self {
+        let shift = mods.contains(KeyMods::SHIFT);
+        let alt = mods.contains(KeyMods::ALT);
+
+        return if (shift && alt) || mods.contains(KeyMods::CTRL) {
+            AnnotationColor::Yellow
+        } else if shift {
+            AnnotationColor::Red
+        } else if alt {
+            AnnotationColor::Blue
+        } else {
+            AnnotationColor::Green
+        };
+    }
+
+    /**
+     * The color's letter in PGN `[%cal]` and `[%csl]` commands.
+     */
+    pub fn pgn_code(&self) -> char {
+        use AnnotationColor::*;
+
+        return match self {
+            Green => 'G',
+            Red => 'R',
+            Blue => 'B',
+            Yellow => 'Y',
+        };
+    }
+
+    pub fn rgba(&self) -> [f32; 4] {
+        use AnnotationColor::*;
+
+        return match self {
+            Green => [0.08, 0.47, 0.11, 0.8],
+            Red => [0.53, 0.12, 0.12, 0.8],
+            Blue => [0., 0.19, 0.53, 0.8],
+            Yellow => [0.9, 0.63, 0.04, 0.8],
+        };
+    }
+}
+
+pub struct Arrow {
+    pub from: ChessLoc,
+    pub to: ChessLoc,
+    pub color: AnnotationColor,
+}
+
+/**
+ * Arrows and marked squares drawn by the user on one position.
+ */
+#[derive(Default)]
+pub struct Annotations {
+    pub arrows: Vec<Arrow>,
+    pub squares: Vec<(ChessLoc, AnnotationColor)>,
+}
+
+impl Annotations {
+    /**
+     * Add an arrow, or remove it if the same arrow is already there. Drawing
+     * over an arrow with another color recolors it.
+     */
+    pub fn toggle_arrow(
+        &mut self,
+        from: ChessLoc,
+        to: ChessLoc,
+        color: AnnotationColor,
+    ) {
+        match self.arrows.iter().position(|a| a.from == from && a.to == to) {
+            Some(i) => if self.arrows[i].color == color {
+                self.arrows.remove(i);
+            } else {
+                self.arrows[i].color = color;
+            },
+            None => self.arrows.push(Arrow { from, to, color }),
+        }
+    }
+
+    /**
+     * Mark a square, with the same toggling rules as
+     * [Annotations::toggle_arrow].
+     */
+    pub fn toggle_square(&mut self, loc: ChessLoc, color: AnnotationColor) {
+        match self.squares.iter().position(|(l, _)| *l == loc) {
+            Some(i) => if self.squares[i].1 == color {
+                self.squares.remove(i);
+            } else {
+                self.squares[i].1 = color;
+            },
+            None => self.squares.push((loc, color)),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.arrows.clear();
+        self.squares.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.arrows.is_empty() && self.squares.is_empty();
+    }
+
+    /**
+     * Format the annotations as the inside of a PGN comment, e.g.
+     * `[%csl Re4][%cal Gg1f3]`.
+     */
+    pub fn to_pgn(&self) -> String {
+        let mut out = String::new();
+
+        if !self.squares.is_empty() {
+            let squares: Vec<String> = self.squares.iter()
+                .map(|(loc, color)| {
+                    format!("{}{}", color.pgn_code(), loc_name(loc))
+                })
+                .collect();
+            out += &format!("[%csl {}]", squares.join(","));
+        }
+
+        if !self.arrows.is_empty() {
+            let arrows: Vec<String> = self.arrows.iter()
+                .map(|a| format!("{}{}{}", a.color.pgn_code(),
+                                 loc_name(&a.from), loc_name(&a.to)))
+                .collect();
+            out += &format!("[%cal {}]", arrows.join(","));
+        }
+
+        return out;
+    }
+}
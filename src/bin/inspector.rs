@@ -0,0 +1,365 @@
+/*!
+ * Proxy between a client and a server that prints every message and points
+ * out protocol violations: messages that do not fit the protocol, illegal
+ * moves and boards that do not match the moves made. The bytes are passed on
+ * unchanged, so the inspector can sit between any two implementations.
+ */
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::Duration;
+
+use chess_network_protocol::*;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use sieric_chess_gui::chess_engine::*;
+use sieric_chess_gui::connection::JsonReader;
+use sieric_chess_gui::local_engine::{LocalGame, Rules};
+use sieric_chess_gui::protocol::*;
+use sieric_chess_gui::transport::*;
+
+const USAGE: &str = "Usage: inspector --server <ADDR> [--port <PORT>]
+
+Pass a game between a client and a server and report protocol violations.
+Point the client at the inspector's port.
+
+Options:
+    --server <ADDR>  Server to pass the client on to
+    --port <PORT>    Port the client connects to (default: 4321)
+    -h, --help       Print this message";
+
+const DEFAULT_PORT: u16 = 4321;
+
+/// Pause between polls when nothing happened
+const IDLE_SLEEP: Duration = Duration::from_millis(5);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Sender {
+    Client,
+    Server,
+}
+
+/**
+ * Follows the game and checks each message against it.
+ */
+struct Inspector {
+    engine: LocalGame,
+    client_handshake: Option<ClientToServerHandshake>,
+    server_handshake: bool,
+    /// The client's move waiting for the server's answer
+    pending: Option<Move>,
+    /// Whether the pending move came out of turn, so it has to be refused
+    out_of_turn: bool,
+    violations: usize,
+}
+
+impl Inspector {
+    fn new() -> Self {
+        return Inspector {
+            engine: LocalGame::new(),
+            client_handshake: None,
+            server_handshake: false,
+            pending: None,
+            out_of_turn: false,
+            violations: 0,
+        };
+    }
+
+    fn violation(&mut self, problem: String) {
+        self.violations += 1;
+        println!("!! {}", problem);
+    }
+
+    /**
+     * Read `message` as a `T`, reporting it if it is not one.
+     */
+    fn parse<T: DeserializeOwned>(&mut self, message: &Value, what: &str)
+        -> Option<T> {
+        return match serde_json::from_value(message.clone()) {
+            Ok(msg) => Some(msg),
+            Err(e) => {
+                self.violation(format!("not a valid {}: {}", what, e));
+                None
+            },
+        };
+    }
+
+    fn is_legal(&self, mv: &Move) -> bool {
+        return proto_moves(&self.engine).contains(mv);
+    }
+
+    /**
+     * Play a move the server reported, checking it first.
+     */
+    fn play(&mut self, mv: &Move) {
+        if !self.is_legal(mv) {
+            self.violation(format!("the server let {} through, it is illegal",
+                                   move_name(mv)));
+            return;
+        }
+        apply_proto_move(&mut self.engine, mv);
+    }
+
+    fn check_board(&mut self, board: &[[Piece; 8]; 8]) {
+        let ours = proto_board(&self.engine);
+        for y in 0..8 {
+            for x in 0..8 {
+                if ours[y][x] != board[y][x] {
+                    self.violation(format!(
+                        "the server has {:?} on {}, should be {:?}",
+                        board[y][x], square_name(x, y), ours[y][x],
+                    ));
+                }
+            }
+        }
+    }
+
+    fn check_moves(&mut self, moves: &[Move]) {
+        /* servers without move generation may send none */
+        if moves.is_empty() {
+            return;
+        }
+        let ours = proto_moves(&self.engine);
+        for mv in moves.iter().filter(|mv| !ours.contains(mv)) {
+            self.violation(format!("the server offers illegal move {}",
+                                   move_name(mv)));
+        }
+        for mv in ours.iter().filter(|mv| !moves.contains(mv)) {
+            self.violation(format!("the server leaves out legal move {}",
+                                   move_name(mv)));
+        }
+    }
+
+    fn client_message(&mut self, message: &Value) {
+        if self.client_handshake.is_none() {
+            let c2sh: Option<ClientToServerHandshake>
+                = self.parse(message, "ClientToServerHandshake");
+            self.client_handshake = c2sh;
+            return;
+        }
+
+        let c2s: ClientToServer = match self.parse(message, "ClientToServer") {
+            Some(c2s) => c2s,
+            None => return,
+        };
+        if let Some(mv) = self.pending.take() {
+            self.violation(format!(
+                "the client sent again before the server answered {}",
+                move_name(&mv),
+            ));
+        }
+        if let ClientToServer::Move(mv) = c2s {
+            let server_white = self.client_handshake.as_ref()
+                .is_some_and(|c2sh| c2sh.server_color == Color::White);
+            self.out_of_turn = self.engine.get_player() == server_white;
+            if self.out_of_turn {
+                println!("   {} is out of turn", move_name(&mv));
+            } else if !self.is_legal(&mv) {
+                println!("   {} is illegal", move_name(&mv));
+            }
+            self.pending = Some(mv);
+        }
+    }
+
+    fn server_message(&mut self, message: &Value) {
+        if !self.server_handshake {
+            let s2ch: ServerToClientHandshake
+                = match self.parse(message, "ServerToClientHandshake") {
+                Some(s2ch) => s2ch,
+                None => return,
+            };
+            self.server_handshake = true;
+
+            let has = |wanted: fn(&Features) -> bool| {
+                s2ch.features.iter().any(wanted)
+            };
+            self.engine = LocalGame::with_rules(Rules {
+                castling: has(|f| matches!(f, Features::Castling)),
+                en_passant: has(|f| matches!(f, Features::EnPassant)),
+            });
+            self.check_board(&s2ch.board);
+            self.check_moves(&s2ch.moves);
+            return;
+        }
+
+        let s2c: ServerToClient = match self.parse(message, "ServerToClient") {
+            Some(s2c) => s2c,
+            None => return,
+        };
+        let pending = self.pending.take();
+        match s2c {
+            ServerToClient::State { board, moves, joever: _, move_made } => {
+                match pending {
+                    Some(mv) if mv != move_made => self.violation(format!(
+                        "the client played {}, the server reports {}",
+                        move_name(&mv), move_name(&move_made),
+                    )),
+                    _ => (),
+                }
+                self.play(&move_made);
+                self.check_board(&board);
+                self.check_moves(&moves);
+            },
+            ServerToClient::Error { board, moves, joever: _, message } => {
+                println!("   the server says: {}", message);
+                let refusable = |mv: &Move| {
+                    self.out_of_turn || !self.is_legal(mv)
+                };
+                if let Some(mv) = pending.filter(|mv| !refusable(mv)) {
+                    self.violation(format!("the server rejected legal move {}",
+                                           move_name(&mv)));
+                }
+                self.check_board(&board);
+                self.check_moves(&moves);
+            },
+            ServerToClient::Resigned { board, joever: _ } => {
+                self.check_board(&board);
+            },
+            ServerToClient::Draw { board, moves } => {
+                self.check_board(&board);
+                self.check_moves(&moves);
+            },
+        }
+    }
+
+    fn inspect(&mut self, from: Sender, message: &Value) {
+        let arrow = match from {
+            Sender::Client => "client -> server",
+            Sender::Server => "server -> client",
+        };
+        let pretty = serde_json::to_string_pretty(message)
+            .unwrap_or_else(|_| message.to_string());
+        println!("{}:\n{}", arrow, pretty);
+
+        match from {
+            Sender::Client => self.client_message(message),
+            Sender::Server => self.server_message(message),
+        }
+    }
+}
+
+/**
+ * Pass what arrived on `src` on to `dst` and inspect the messages in it.
+ * Returns [false] if nothing arrived and fails once `src` is closed.
+ */
+fn pump(
+    from: Sender,
+    src: &mut TcpStream,
+    dst: &mut TcpStream,
+    splitter: &mut JsonReader,
+    inspector: &mut Inspector,
+) -> io::Result<bool> {
+    let mut chunk = [0u8; 4096];
+    let n = match src.read(&mut chunk) {
+        Ok(0) => return Err(io::Error::new(
+            ErrorKind::UnexpectedEof,
+            "the connection was closed",
+        )),
+        Ok(n) => n,
+        Err(e) if e.kind() == ErrorKind::WouldBlock
+            || e.kind() == ErrorKind::TimedOut => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    dst.write_all(&chunk[..n])?;
+    dst.flush()?;
+
+    splitter.extend(&chunk[..n]);
+    loop {
+        match splitter.parse::<Value>() {
+            Ok(Some(message)) => inspector.inspect(from, &message),
+            Ok(None) => break,
+            Err(e) => {
+                let junk = splitter.take_junk();
+                inspector.violation(format!(
+                    "invalid JSON ({}): {}", e, String::from_utf8_lossy(&junk),
+                ));
+            },
+        }
+    }
+
+    return Ok(true);
+}
+
+/**
+ * Pass messages both ways until either side closes the connection.
+ */
+fn relay(
+    client: &mut TcpStream,
+    server: &mut TcpStream,
+    inspector: &mut Inspector,
+) -> io::Result<()> {
+    let mut client_splitter = JsonReader::default();
+    let mut server_splitter = JsonReader::default();
+    loop {
+        let client_busy = pump(Sender::Client, client, server,
+                               &mut client_splitter, inspector)?;
+        let server_busy = pump(Sender::Server, server, client,
+                               &mut server_splitter, inspector)?;
+        if !client_busy && !server_busy {
+            thread::sleep(IDLE_SLEEP);
+        }
+    }
+}
+
+fn parse_args() -> Result<(SocketAddr, u16), String> {
+    let mut args = std::env::args().skip(1);
+    let mut server = None;
+    let mut port = DEFAULT_PORT;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next()
+            .ok_or_else(|| format!("Missing value for {}", arg));
+
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            },
+            "--server" => {
+                let value = value()?;
+                server = value.to_socket_addrs().ok()
+                    .and_then(|mut addrs| addrs.next());
+                if server.is_none() {
+                    return Err(format!("Invalid address: {}", value));
+                }
+            },
+            "--port" => {
+                let value = value()?;
+                port = value.parse()
+                    .map_err(|_| format!("Invalid port: {}", value))?;
+            },
+            _ => return Err(format!("Unknown argument: {}", arg)),
+        }
+    }
+
+    let server = server.ok_or_else(|| String::from("Missing --server"))?;
+    return Ok((server, port));
+}
+
+fn main() -> io::Result<()> {
+    let (server_addr, port) = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        },
+    };
+
+    let mut acceptor = TcpAcceptor::bind(port)?;
+    println!("Waiting for a client on {}", acceptor.describe());
+    let mut client = acceptor.accept()?;
+    let mut server = TcpConnector::new(server_addr).connect()?;
+    println!("Passing it on to {}", server_addr);
+
+    let mut inspector = Inspector::new();
+    if let Err(e) = relay(&mut client, &mut server, &mut inspector) {
+        println!("Done: {}", e);
+    }
+    println!("{} protocol violations", inspector.violations);
+    if inspector.violations > 0 {
+        std::process::exit(1);
+    }
+    return Ok(());
+}
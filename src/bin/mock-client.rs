@@ -0,0 +1,81 @@
+/*!
+ * Client that follows a script instead of the rules, to see how servers from
+ * other implementations handle edge cases like illegal moves, moves out of
+ * turn or resigning early. See [sieric_chess_gui::script] for what scripts
+ * can do.
+ */
+
+use std::io;
+use std::net::ToSocketAddrs;
+
+use sieric_chess_gui::script::{self, Role, Runner};
+use sieric_chess_gui::transport::{Connector, TcpConnector};
+
+const USAGE: &str = "Usage: mock-client <SCRIPT> [--connect <ADDR>]
+
+Connect to a server and run SCRIPT against it.
+
+Options:
+    --connect <ADDR> Server to connect to (default: 127.0.0.1:1234)
+    -h, --help       Print this message";
+
+fn parse_args() -> Result<(String, String), String> {
+    let mut args = std::env::args().skip(1);
+    let mut script = None;
+    let mut addr = String::from("127.0.0.1:1234");
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            },
+            "--connect" => {
+                addr = args.next()
+                    .ok_or_else(|| format!("Missing value for {}", arg))?;
+            },
+            _ if script.is_none() && !arg.starts_with('-') => {
+                script = Some(arg);
+            },
+            _ => return Err(format!("Unknown argument: {}", arg)),
+        }
+    }
+
+    let script = script.ok_or_else(|| String::from("Missing script"))?;
+    return Ok((script, addr));
+}
+
+fn main() -> io::Result<()> {
+    let (path, addr) = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        },
+    };
+
+    let text = std::fs::read_to_string(&path)?;
+    let lines = match script::parse(Role::Client, &text) {
+        Ok(lines) => lines,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(2);
+        },
+    };
+
+    let addr = addr.to_socket_addrs()?.next().ok_or_else(|| io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("No address found for {}", addr),
+    ))?;
+    let mut connector = TcpConnector::new(addr);
+    println!("Connecting to {}", connector.describe());
+    let failures = Runner::new(connector.connect()?, Role::Client)
+        .run(&lines)?;
+
+    if failures > 0 {
+        println!("{} expectations failed", failures);
+        std::process::exit(1);
+    }
+    println!("All expectations met");
+    return Ok(());
+}
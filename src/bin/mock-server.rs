@@ -0,0 +1,78 @@
+/*!
+ * Server that follows a script instead of the rules, to see how clients from
+ * other implementations handle edge cases. See [sieric_chess_gui::script]
+ * for what scripts can do.
+ */
+
+use std::io;
+
+use sieric_chess_gui::script::{self, Role, Runner};
+use sieric_chess_gui::transport::{Acceptor, TcpAcceptor};
+
+const USAGE: &str = "Usage: mock-server <SCRIPT> [--port <PORT>]
+
+Wait for a client and run SCRIPT against it.
+
+Options:
+    --port <PORT>    Port to listen on (default: 1234)
+    -h, --help       Print this message";
+
+const DEFAULT_PORT: u16 = 1234;
+
+fn parse_args() -> Result<(String, u16), String> {
+    let mut args = std::env::args().skip(1);
+    let mut script = None;
+    let mut port = DEFAULT_PORT;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            },
+            "--port" => {
+                let value = args.next()
+                    .ok_or_else(|| format!("Missing value for {}", arg))?;
+                port = value.parse()
+                    .map_err(|_| format!("Invalid port: {}", value))?;
+            },
+            _ if script.is_none() && !arg.starts_with('-') => {
+                script = Some(arg);
+            },
+            _ => return Err(format!("Unknown argument: {}", arg)),
+        }
+    }
+
+    let script = script.ok_or_else(|| String::from("Missing script"))?;
+    return Ok((script, port));
+}
+
+fn main() -> io::Result<()> {
+    let (path, port) = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        },
+    };
+
+    let text = std::fs::read_to_string(&path)?;
+    let lines = match script::parse(Role::Server, &text) {
+        Ok(lines) => lines,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(2);
+        },
+    };
+
+    let mut acceptor = TcpAcceptor::bind(port)?;
+    println!("Waiting for a client on {}", acceptor.describe());
+    let failures = Runner::new(acceptor.accept()?, Role::Server).run(&lines)?;
+
+    if failures > 0 {
+        println!("{} expectations failed", failures);
+        std::process::exit(1);
+    }
+    println!("All expectations met");
+    return Ok(());
+}
@@ -0,0 +1,123 @@
+/*!
+ * Play back a recording made with `--record` as the peer of the side that
+ * recorded it, so interop problems with other implementations can be
+ * reproduced without them. A recording made by a client is played back as a
+ * server waiting for a client, and one made by a host or server as a client
+ * connecting to it.
+ */
+
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+
+use sieric_chess_gui::recorder;
+use sieric_chess_gui::transport::*;
+
+const USAGE: &str = "Usage: replay <LOG> [OPTIONS]
+
+Play back a recording as the other side of the connection.
+
+Options:
+    --port <PORT>    Port to wait for the client on when playing back a
+                     client's recording (default: 1234)
+    --connect <ADDR> Server to connect to when playing back a host's
+                     recording (default: 127.0.0.1:1234)
+    --connection <N> Which of the recorded connections to play back,
+                     counting from 0 (default: the first one)
+    -h, --help       Print this message";
+
+const DEFAULT_PORT: u16 = 1234;
+
+/// How long to wait for each message, the peer may have a human at it
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(300);
+
+struct Options {
+    log: String,
+    port: u16,
+    connect: String,
+    connection: Option<usize>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut args = std::env::args().skip(1);
+    let mut log = None;
+    let mut port = DEFAULT_PORT;
+    let mut connect = format!("127.0.0.1:{}", DEFAULT_PORT);
+    let mut connection = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next()
+            .ok_or_else(|| format!("Missing value for {}", arg));
+
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            },
+            "--port" => {
+                let value = value()?;
+                port = value.parse()
+                    .map_err(|_| format!("Invalid port: {}", value))?;
+            },
+            "--connect" => connect = value()?,
+            "--connection" => {
+                let value = value()?;
+                connection = Some(value.parse()
+                    .map_err(|_| format!("Invalid connection: {}", value))?);
+            },
+            _ if log.is_none() && !arg.starts_with('-') => log = Some(arg),
+            _ => return Err(format!("Unknown argument: {}", arg)),
+        }
+    }
+
+    return Ok(Options {
+        log: log.ok_or_else(|| String::from("Missing recording"))?,
+        port,
+        connect,
+        connection,
+    });
+}
+
+fn resolve(addr: &str) -> io::Result<SocketAddr> {
+    return addr.to_socket_addrs()?.next().ok_or_else(|| io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("No address found for {}", addr),
+    ));
+}
+
+fn main() -> io::Result<()> {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        },
+    };
+
+    let records = recorder::load(&options.log)?;
+    let connection = match options.connection {
+        Some(connection) => connection,
+        None => records.first().map_or(0, |record| record.connection),
+    };
+    let by_client = recorder::recorded_by_client(&records, connection);
+
+    let mut stream = if by_client {
+        let mut acceptor = TcpAcceptor::bind(options.port)?;
+        println!("Waiting for a client on {}", acceptor.describe());
+        acceptor.accept()?
+    } else {
+        let mut connector = TcpConnector::new(resolve(&options.connect)?);
+        println!("Connecting to {}", connector.describe());
+        connector.connect()?
+    };
+
+    let mismatches = recorder::replay(&records, connection, &mut stream,
+                                      MESSAGE_TIMEOUT)?;
+    if mismatches == 0 {
+        println!("The peer behaved as recorded");
+        return Ok(());
+    }
+
+    println!("{} messages differed from the recording", mismatches);
+    std::process::exit(1);
+}
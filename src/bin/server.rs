@@ -0,0 +1,414 @@
+/*!
+ * Headless server that referees a game between two clients, so two players
+ * can meet on a neutral machine. After a game ends the server waits for the
+ * next two players.
+ */
+
+use std::io::{self, Write};
+use std::thread;
+use std::time::Duration;
+
+use chess_network_protocol::*;
+use serde::Serialize;
+
+use sieric_chess_gui::chess_engine::*;
+use sieric_chess_gui::connection::{JsonReader, PendingHandshake};
+use sieric_chess_gui::local_engine::{IllegalMove, LocalGame, Rules};
+use sieric_chess_gui::protocol::*;
+use sieric_chess_gui::recorder::{Recorder, RecordingAcceptor};
+use sieric_chess_gui::spectators::Spectators;
+use sieric_chess_gui::transport::{Acceptor, TcpAcceptor};
+
+const USAGE: &str = "Usage: server [OPTIONS]
+
+Referee games between two clients.
+
+Options:
+    --port <PORT>    Port to listen on (default: 1234)
+    --spectator-port <PORT>
+                     Port spectators connect to (default: one after
+                     the game's port)
+    --no-castling    Play without castling
+    --no-en-passant  Play without en passant
+    --record <FILE>  Record all messages to FILE, one JSON object per
+                     line, see the replay tool
+    -h, --help       Print this message";
+
+const DEFAULT_PORT: u16 = 1234;
+
+/// Pause between polls when nothing happened
+const IDLE_SLEEP: Duration = Duration::from_millis(5);
+
+/**
+ * A connected client, or the place for one.
+ */
+struct Seat<S> {
+    stream: Option<S>,
+    reader: JsonReader,
+}
+
+impl<S> Default for Seat<S> {
+    fn default() -> Self {
+        return Seat { stream: None, reader: JsonReader::default() };
+    }
+}
+
+fn seat_index(white: bool) -> usize {
+    return if white { 0 } else { 1 };
+}
+
+fn seat_name(i: usize) -> &'static str {
+    return if i == 0 { "white" } else { "black" };
+}
+
+/**
+ * What the game's result is, taking resignations and agreed draws into
+ * account.
+ */
+fn joever(engine: &mut LocalGame, result: Option<ChessState>) -> Joever {
+    return proto_joever(match result {
+        Some(result) => result,
+        None => engine.get_state(),
+    });
+}
+
+fn handshake(engine: &mut LocalGame, result: Option<ChessState>)
+    -> ServerToClientHandshake {
+    return ServerToClientHandshake {
+        features: proto_features(engine.get_rules()),
+        board: proto_board(engine),
+        moves: proto_moves(engine),
+        joever: joever(engine, result),
+    };
+}
+
+struct Server<'a, A: Acceptor> {
+    acceptor: &'a mut A,
+    /// Kept from game to game, every update contains the whole board
+    spectators: &'a mut Spectators<A>,
+    engine: LocalGame,
+    /// White's seat first
+    seats: [Seat<A::Stream>; 2],
+    /// Clients whose handshake has not arrived yet
+    pending: Vec<PendingHandshake<A::Stream>>,
+    /// Seat of the player who offered a draw
+    draw_offer: Option<usize>,
+    /// Set once the game ended by resignation or agreement
+    result: Option<ChessState>,
+}
+
+impl<'a, A: Acceptor> Server<'a, A> {
+    fn new(
+        acceptor: &'a mut A,
+        spectators: &'a mut Spectators<A>,
+        rules: Rules,
+    ) -> Self {
+        return Server {
+            acceptor,
+            spectators,
+            engine: LocalGame::with_rules(rules),
+            seats: [Seat::default(), Seat::default()],
+            pending: Vec::new(),
+            draw_offer: None,
+            result: None,
+        };
+    }
+
+    fn joever(&mut self) -> Joever {
+        return joever(&mut self.engine, self.result);
+    }
+
+    fn send(&mut self, i: usize, msg: &impl Serialize) {
+        let stream = match self.seats[i].stream.as_mut() {
+            Some(stream) => stream,
+            None => return,
+        };
+
+        let sent = serde_json::to_writer(&mut *stream, msg)
+            .map_err(io::Error::from)
+            .and_then(|_| stream.flush());
+        if let Err(e) = sent {
+            println!("Lost {}: {}", seat_name(i), e);
+            self.seats[i] = Seat::default();
+        }
+    }
+
+    /**
+     * Send `msg` to both players and all spectators.
+     */
+    fn broadcast(&mut self, msg: &impl Serialize) {
+        self.send(0, msg);
+        self.send(1, msg);
+
+        let watching = self.spectators.len();
+        self.spectators.broadcast(msg);
+        for _ in self.spectators.len()..watching {
+            println!("A spectator left");
+        }
+    }
+
+    fn send_error(&mut self, i: usize, message: &str) {
+        let msg = ServerToClient::Error {
+            board: proto_board(&self.engine),
+            moves: proto_moves(&self.engine),
+            joever: self.joever(),
+            message: String::from(message),
+        };
+        self.send(i, &msg);
+    }
+
+    /**
+     * Answer a client's handshake and give it the seat it asked for. Clients
+     * asking for a taken seat are turned away.
+     */
+    fn seat(&mut self, pending: PendingHandshake<A::Stream>,
+            c2sh: ClientToServerHandshake) -> io::Result<()> {
+        let (mut stream, reader) = pending.into_parts();
+
+        /* the client asks which color the server plays for it */
+        let i = seat_index(c2sh.server_color == Color::Black);
+        if self.seats[i].stream.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is already taken", seat_name(i)),
+            ));
+        }
+
+        let s2ch = handshake(&mut self.engine, self.result);
+        serde_json::to_writer(&mut stream, &s2ch)?;
+        stream.flush()?;
+
+        println!("{} joined", seat_name(i));
+        self.seats[i] = Seat { stream: Some(stream), reader };
+        return Ok(());
+    }
+
+    fn handle(&mut self, i: usize, msg: ClientToServer) {
+        let other = 1 - i;
+        if self.seats[other].stream.is_none() {
+            self.send_error(i, "waiting for an opponent");
+            return;
+        }
+
+        match msg {
+            ClientToServer::Move(mv) => {
+                let checked = if self.joever() != Joever::Ongoing {
+                    Err(IllegalMove::GameOver)
+                } else if seat_index(self.engine.get_player()) != i {
+                    Err(IllegalMove::NotYourTurn)
+                } else {
+                    check_proto_move(&self.engine, &mv)
+                };
+                if let Err(reason) = checked {
+                    self.send_error(i, &reason.to_string());
+                    return;
+                }
+
+                apply_proto_move(&mut self.engine, &mv);
+                self.draw_offer = None;
+                println!("{} played {:?}", seat_name(i), mv);
+
+                let msg = ServerToClient::State {
+                    board: proto_board(&self.engine),
+                    moves: proto_moves(&self.engine),
+                    joever: self.joever(),
+                    move_made: mv,
+                };
+                self.broadcast(&msg);
+            },
+            ClientToServer::Resign => {
+                println!("{} resigned", seat_name(i));
+                self.result = Some(if i == 0 {
+                    ChessState::JoeverBlack
+                } else {
+                    ChessState::JoeverWhite
+                });
+
+                let msg = ServerToClient::Resigned {
+                    board: proto_board(&self.engine),
+                    joever: self.joever(),
+                };
+                self.broadcast(&msg);
+            },
+            /* a draw needs both players to ask for it */
+            ClientToServer::Draw => if self.draw_offer == Some(other) {
+                println!("Draw agreed");
+                self.result = Some(ChessState::JoeverDraw);
+
+                let msg = ServerToClient::Draw {
+                    board: proto_board(&self.engine),
+                    moves: proto_moves(&self.engine),
+                };
+                self.broadcast(&msg);
+            } else {
+                println!("{} offers a draw", seat_name(i));
+                self.draw_offer = Some(i);
+            },
+        }
+    }
+
+    /**
+     * Handle everything that arrived since the last call. Returns [true] if
+     * anything happened.
+     */
+    fn poll(&mut self) -> io::Result<bool> {
+        let mut busy = false;
+
+        while let Some(stream) = self.acceptor.try_accept()? {
+            busy = true;
+            self.pending.push(PendingHandshake::new(stream));
+        }
+
+        /* clients are only seated once their handshake is complete */
+        let mut i = 0;
+        while i < self.pending.len() {
+            let seated = match self.pending[i].poll() {
+                Ok(Some(c2sh)) => {
+                    let pending = self.pending.swap_remove(i);
+                    self.seat(pending, c2sh)
+                },
+                Ok(None) => {
+                    i += 1;
+                    continue;
+                },
+                Err(e) => {
+                    self.pending.swap_remove(i);
+                    Err(e)
+                },
+            };
+
+            busy = true;
+            if let Err(e) = seated {
+                println!("Turned a client away: {}", e);
+            }
+        }
+
+        let engine = &mut self.engine;
+        let result = self.result;
+        let watching = self.spectators.len();
+        self.spectators.accept(|| handshake(engine, result));
+        for _ in watching..self.spectators.len() {
+            println!("A spectator joined");
+        }
+
+        for i in 0..2 {
+            let seat = &mut self.seats[i];
+            let stream = match seat.stream.as_mut() {
+                Some(stream) => stream,
+                None => continue,
+            };
+
+            match seat.reader.poll::<ClientToServer, _>(stream) {
+                Ok(Some(msg)) => {
+                    busy = true;
+                    self.handle(i, msg);
+                },
+                Ok(None) => (),
+                /* the reader drops what it could not read */
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                    busy = true;
+                    self.send_error(i, &format!("invalid message: {}", e));
+                },
+                Err(e) => {
+                    /* the seat stays free for the player to come back */
+                    println!("Lost {}: {}", seat_name(i), e);
+                    self.seats[i] = Seat::default();
+                },
+            }
+        }
+
+        return Ok(busy);
+    }
+
+    /**
+     * Referee one game until it is over.
+     */
+    fn run(&mut self) -> io::Result<()> {
+        println!("Waiting for players on {}, spectators on {}",
+                 self.acceptor.describe(), self.spectators.describe());
+        while matches!(self.joever(), Joever::Ongoing) {
+            if !self.poll()? {
+                thread::sleep(IDLE_SLEEP);
+            }
+        }
+
+        println!("Game over: {:?}", self.joever());
+        return Ok(());
+    }
+}
+
+struct Options {
+    port: u16,
+    spectator_port: u16,
+    rules: Rules,
+    record: Option<String>,
+}
+
+/**
+ * Parse the command line.
+ */
+fn parse_args() -> Result<Options, String> {
+    let mut args = std::env::args().skip(1);
+    let mut port = DEFAULT_PORT;
+    let mut spectator_port = None;
+    let mut rules = Rules::default();
+    let mut record = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            },
+            "--no-castling" => rules.castling = false,
+            "--no-en-passant" => rules.en_passant = false,
+            "--record" => record = Some(args.next()
+                .ok_or_else(|| format!("Missing value for {}", arg))?),
+            "--port" | "--spectator-port" => {
+                let value = args.next()
+                    .ok_or_else(|| format!("Missing value for {}", arg))?;
+                let value = value.parse()
+                    .map_err(|_| format!("Invalid port: {}", value))?;
+                if arg == "--port" {
+                    port = value;
+                } else {
+                    spectator_port = Some(value);
+                }
+            },
+            _ => return Err(format!("Unknown argument: {}", arg)),
+        }
+    }
+
+    let spectator_port = match spectator_port {
+        Some(port) => port,
+        None => port.checked_add(1)
+            .ok_or_else(|| String::from("No port left for spectators"))?,
+    };
+    return Ok(Options { port, spectator_port, rules, record });
+}
+
+fn main() -> io::Result<()> {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        },
+    };
+
+    let recorder = match &options.record {
+        Some(path) => Some(Recorder::create(path)?),
+        None => None,
+    };
+    let mut acceptor = RecordingAcceptor::new(
+        TcpAcceptor::bind(options.port)?,
+        recorder.clone(),
+    );
+    let mut spectators = Spectators::new(RecordingAcceptor::new(
+        TcpAcceptor::bind(options.spectator_port)?,
+        recorder,
+    ));
+    loop {
+        Server::new(&mut acceptor, &mut spectators, options.rules).run()?;
+    }
+}
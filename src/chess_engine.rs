@@ -9,6 +9,7 @@ pub type ChessLoc = (i32, i32);
 /**
  * Representation of a state in the game.
  */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum ChessState {
     /// The game is still ongoing
     Ongoing,
@@ -22,6 +23,7 @@ pub enum ChessState {
     JoeverBlack,
 }
 
+#[derive(Clone, Copy, Debug)]
 pub struct ChessMove {
     pub from: ChessLoc,
     pub to: ChessLoc,
@@ -52,6 +54,57 @@ pub trait ChessGame {
         return true;
     }
 
+    /**
+     * Return [true] if the game starts by waiting for the opponent's move,
+     * e.g. when playing black online.
+     */
+    fn opponent_starts(&self) -> bool {
+        return false;
+    }
+
+    /**
+     * Return [true] if the user only watches the game and cannot move.
+     */
+    fn is_spectating(&self) -> bool {
+        return false;
+    }
+
+    /**
+     * Return the rules the game is played by, one line each, so the user knows
+     * what to expect. Online games depend on what the server supports.
+     */
+    fn rules(&self) -> Vec<String> {
+        return vec![String::from("Standard chess rules")];
+    }
+
+    /**
+     * Return a message for the user if there is one, e.g. why the last move
+     * was rejected by the server. Each message is only returned once.
+     */
+    fn take_message(&mut self) -> Option<String> {
+        return None;
+    }
+
+    /**
+     * Return [false] once the connection to the opponent is lost. Local games
+     * are always connected.
+     */
+    fn is_connected(&self) -> bool {
+        return true;
+    }
+
+    /**
+     * Connect to the opponent again after the connection was lost. On success
+     * returns [true] if the opponent is to move. Only games started by
+     * connecting to someone else can do this.
+     */
+    fn reconnect(&mut self) -> std::io::Result<bool> {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "this game cannot reconnect",
+        ));
+    }
+
     /**
      * Return the piece at `loc`'s color ([true] for white, [false] for black)
      * and the piece's name in chess notation.
@@ -67,4 +120,77 @@ pub trait ChessGame {
      * Return the current state of the game.
      */
     fn get_state(&mut self) -> ChessState;
+
+    /**
+     * Return [true] if the king of the given color ([true] for white, [false]
+     * for black) is attacked. The default implementation only relies on
+     * [ChessGame::get_piece].
+     */
+    fn in_check(&mut self, white: bool) -> bool {
+        let mut king = None;
+        for y in 0..8 {
+            for x in 0..8 {
+                let (piece_white, name) = self.get_piece(&(x, y));
+                if piece_white == white && name == "K" {
+                    king = Some((x, y));
+                }
+            }
+        }
+
+        let (kx, ky) = match king {
+            Some(loc) => loc,
+            None => return false,
+        };
+        let on_board = |x: i32, y: i32| {
+            (0..8).contains(&x) && (0..8).contains(&y)
+        };
+
+        for (dx, dy) in [(1, 2), (2, 1), (2, -1), (1, -2),
+                         (-1, -2), (-2, -1), (-2, 1), (-1, 2)] {
+            if !on_board(kx + dx, ky + dy) {
+                continue;
+            }
+            let (piece_white, name) = self.get_piece(&(kx + dx, ky + dy));
+            if piece_white != white && name == "N" {
+                return true;
+            }
+        }
+
+        /* enemy pawns attack from the king's forward diagonals */
+        let forward = if white { 1 } else { -1 };
+
+        for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1),
+                         (1, 1), (1, -1), (-1, 1), (-1, -1)] {
+            let diagonal = dx != 0 && dy != 0;
+            let (mut x, mut y) = (kx + dx, ky + dy);
+            let mut dist = 1;
+
+            while on_board(x, y) {
+                let (piece_white, name) = self.get_piece(&(x, y));
+                if name.trim().is_empty() {
+                    x += dx;
+                    y += dy;
+                    dist += 1;
+                    continue;
+                }
+
+                if piece_white != white {
+                    let attacks = match name.as_str() {
+                        "Q" => true,
+                        "R" => !diagonal,
+                        "B" => diagonal,
+                        "K" => dist == 1,
+                        "P" => dist == 1 && diagonal && dy == forward,
+                        _ => false,
+                    };
+                    if attacks {
+                        return true;
+                    }
+                }
+                break;
+            }
+        }
+
+        return false;
+    }
 }
@@ -0,0 +1,135 @@
+use chess_network_protocol::Color;
+
+use sieric_chess_gui::ai_engine::MAX_AI_DEPTH;
+
+pub const USAGE: &str = "Usage: sieric-chess-gui [OPTIONS]
+
+Without options the game starts in the main menu.
+
+Options:
+    --local          Start a local game
+    --ai-depth <N>   With --local, play against the computer, which
+                     looks N moves ahead, 1 to 3
+    --host <PORT>    Host a game on PORT and wait for an opponent, or
+                     on a Unix socket given as unix:<PATH>
+    --join <ADDR>    Join the game at ADDR, e.g. 127.0.0.1:1234 or
+                     unix:/tmp/chess.sock
+    --spectate <ADDR>
+                     Watch the game whose spectator port is at ADDR,
+                     one port after the game's, e.g. 127.0.0.1:1235
+    --color <COLOR>  Color to play when joining or against the
+                     computer, white or black (default: white)
+    --record <FILE>  Record the messages of online games to FILE, one
+                     JSON object per line, see the replay tool
+    -h, --help       Print this message
+
+Starting from a FEN position (--fen) is not available, the chess engine
+can only start from the initial position.";
+
+/**
+ * What to do right after starting.
+ */
+pub enum Launch {
+    Menu,
+    Help,
+    Local,
+    /// Host a game on the given port, still to be validated
+    Host(String),
+    /// Join a game at the given address, still to be resolved
+    Join(String),
+    /// Watch a game at the given address, still to be resolved
+    Spectate(String),
+}
+
+pub struct Args {
+    pub launch: Launch,
+    /// The color played when joining a game or against the computer
+    pub color: Color,
+    /// How many moves the computer looks ahead, [None] to play locally
+    /// without it
+    pub ai_depth: Option<u32>,
+    /// File to record the network traffic to
+    pub record: Option<String>,
+}
+
+fn parse_ai_depth(depth: &str) -> Result<u32, String> {
+    return match depth.parse() {
+        Ok(depth) if (1..=MAX_AI_DEPTH).contains(&depth) => Ok(depth),
+        _ => Err(format!("Invalid AI depth: {}, must be 1 to {}", depth,
+                         MAX_AI_DEPTH)),
+    };
+}
+
+fn parse_color(color: &str) -> Result<Color, String> {
+    return match color.to_lowercase().as_str() {
+        "white" | "w" => Ok(Color::White),
+        "black" | "b" => Ok(Color::Black),
+        _ => Err(format!("Invalid color: {}", color)),
+    };
+}
+
+/**
+ * Parse the command line arguments, not including the program name.
+ */
+pub fn parse_args(mut args: impl Iterator<Item = String>)
+    -> Result<Args, String> {
+    let mut out = Args {
+        launch: Launch::Menu,
+        color: Color::White,
+        ai_depth: None,
+        record: None,
+    };
+    let mut color_given = false;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next()
+            .ok_or_else(|| format!("Missing value for {}", arg));
+
+        let launch = match arg.as_str() {
+            "-h" | "--help" => {
+                out.launch = Launch::Help;
+                return Ok(out);
+            },
+            "--local" => Launch::Local,
+            "--host" => Launch::Host(value()?),
+            "--join" => Launch::Join(value()?),
+            "--spectate" => Launch::Spectate(value()?),
+            "--color" => {
+                out.color = parse_color(&value()?)?;
+                color_given = true;
+                continue;
+            },
+            "--ai-depth" => {
+                out.ai_depth = Some(parse_ai_depth(&value()?)?);
+                continue;
+            },
+            "--record" => {
+                out.record = Some(value()?);
+                continue;
+            },
+            "--fen" => return Err(String::from(
+                "--fen is not supported, the chess engine can only start \
+                 from the initial position",
+            )),
+            _ => return Err(format!("Unknown argument: {}", arg)),
+        };
+
+        if !matches!(out.launch, Launch::Menu) {
+            return Err(String::from("Only one game mode can be given"));
+        }
+        out.launch = launch;
+    }
+
+    let local = matches!(out.launch, Launch::Local);
+    if out.ai_depth.is_some() && !local {
+        return Err(String::from("--ai-depth can only be used with --local"));
+    }
+    let against_ai = local && out.ai_depth.is_some();
+    if color_given && !matches!(out.launch, Launch::Join(_)) && !against_ai {
+        return Err(String::from("--color can only be used with --join or \
+                                 --ai-depth, the joining player picks the \
+                                 colors"));
+    }
+
+    return Ok(out);
+}
@@ -0,0 +1,259 @@
+use std::io::{self, Read, ErrorKind};
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+
+/// How long to try connecting to a server
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for messages that should arrive right away, e.g. the
+/// handshake or the answer to a move
+pub const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a single read may block, keeps polling from stalling the frame
+pub const POLL_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// Largest message accepted, far more than any board with all its moves
+pub const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// How long a message may take to arrive once it started
+pub const MESSAGE_DEADLINE: Duration = Duration::from_secs(10);
+
+/**
+ * Reads JSON values from a stream without blocking. Bytes are buffered until a
+ * complete value has arrived, so a message split across several reads is not
+ * lost. Reads on the stream should only block for a moment, see
+ * [crate::transport].
+ *
+ * Messages that are not valid JSON, do not have the expected form, are larger
+ * than [MAX_MESSAGE_SIZE] or take longer than [MESSAGE_DEADLINE] fail with
+ * [ErrorKind::InvalidData]. They are dropped, so reading can go on after, and
+ * are kept until the next failure for [JsonReader::take_junk].
+ */
+#[derive(Default)]
+pub struct JsonReader {
+    buf: Vec<u8>,
+    /// When the first byte of the message being read arrived
+    started: Option<Instant>,
+    /// The bytes dropped by the last failure
+    junk: Vec<u8>,
+}
+
+fn invalid(message: impl std::fmt::Display) -> io::Error {
+    return io::Error::new(ErrorKind::InvalidData, message.to_string());
+}
+
+impl JsonReader {
+    /**
+     * Return the next value if one has arrived completely. Fails if the peer
+     * closed the connection or sent an invalid message.
+     */
+    pub fn poll<T, R>(&mut self, stream: &mut R) -> io::Result<Option<T>>
+        where T: DeserializeOwned, R: Read {
+        if let Some(value) = self.parse()? {
+            return Ok(Some(value));
+        }
+
+        let mut chunk = [0u8; 4096];
+        let n = match stream.read(&mut chunk) {
+            Ok(0) => return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "the peer closed the connection",
+            )),
+            Ok(n) => n,
+            Err(e) if is_timeout(&e) => return Ok(None),
+            Err(e) if e.kind() == ErrorKind::Interrupted => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        self.extend(&chunk[..n]);
+
+        return self.parse();
+    }
+
+    /**
+     * Wait for the next value, failing if none arrives within `timeout`.
+     */
+    pub fn read<T, R>(&mut self, stream: &mut R, timeout: Duration)
+        -> io::Result<T>
+        where T: DeserializeOwned, R: Read {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(value) = self.poll(stream)? {
+                return Ok(value);
+            }
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(
+                    ErrorKind::TimedOut,
+                    "the peer did not answer in time",
+                ));
+            }
+        }
+    }
+
+    /**
+     * Buffer bytes read from the stream by someone else.
+     */
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+        self.skip_whitespace();
+    }
+
+    /**
+     * Drop the whitespace between messages, so that only actual messages
+     * start the clock.
+     */
+    fn skip_whitespace(&mut self) {
+        let start = self.buf.iter()
+            .position(|b| !b.is_ascii_whitespace())
+            .unwrap_or(self.buf.len());
+        self.buf.drain(..start);
+
+        if self.buf.is_empty() {
+            self.started = None;
+        } else if self.started.is_none() {
+            self.started = Some(Instant::now());
+        }
+    }
+
+    /**
+     * Drop the first `n` bytes buffered, keeping them as junk.
+     */
+    fn discard(&mut self, n: usize) {
+        self.junk = self.buf.drain(..n).collect();
+        self.started = None;
+        self.skip_whitespace();
+    }
+
+    /**
+     * Return the bytes dropped by the last failed message.
+     */
+    pub fn take_junk(&mut self) -> Vec<u8> {
+        return std::mem::take(&mut self.junk);
+    }
+
+    /**
+     * Return where the next message may start after the syntax error `e`:
+     * the first `{` or `"` after the error, as messages are objects, or
+     * strings for the variants without data. The messages after a broken one
+     * are kept this way.
+     */
+    fn resync_point(&self, e: &serde_json::Error) -> usize {
+        let line_start = match e.line() {
+            0 | 1 => 0,
+            line => self.buf.iter()
+                .enumerate()
+                .filter(|&(_, &b)| b == b'\n')
+                .nth(line - 2)
+                .map_or(self.buf.len(), |(i, _)| i + 1),
+        };
+
+        /* the column points at the byte that broke the message */
+        let after = (line_start + e.column()).clamp(1, self.buf.len());
+        return self.buf[after..].iter()
+            .position(|&b| b == b'{' || b == b'"')
+            .map_or(self.buf.len(), |i| after + i);
+    }
+
+    /**
+     * Return the next value among the bytes buffered so far, without reading.
+     */
+    pub fn parse<T: DeserializeOwned>(&mut self) -> io::Result<Option<T>> {
+        let mut values = serde_json::Deserializer::from_slice(&self.buf)
+            .into_iter::<serde_json::Value>();
+
+        let value = match values.next() {
+            Some(Ok(value)) => {
+                let used = values.byte_offset();
+                if used > MAX_MESSAGE_SIZE {
+                    self.discard(used);
+                    return Err(invalid(format!(
+                        "message larger than {} bytes", MAX_MESSAGE_SIZE,
+                    )));
+                }
+                self.buf.drain(..used);
+                self.started = None;
+                self.skip_whitespace();
+                value
+            },
+            /* the rest of the value has not arrived yet */
+            Some(Err(e)) if e.is_eof() => return self.check_incomplete(),
+            Some(Err(e)) => {
+                self.discard(self.resync_point(&e));
+                return Err(invalid(e));
+            },
+            None => return Ok(None),
+        };
+
+        /* the value is used up either way, the next one can still be fine */
+        return serde_json::from_value(value).map(Some).map_err(invalid);
+    }
+
+    /**
+     * Give up on a message that is taking too much space or time.
+     */
+    fn check_incomplete<T>(&mut self) -> io::Result<Option<T>> {
+        if self.buf.len() > MAX_MESSAGE_SIZE {
+            self.discard(self.buf.len());
+            return Err(invalid(format!(
+                "message larger than {} bytes", MAX_MESSAGE_SIZE,
+            )));
+        }
+        if self.started.is_some_and(|t| t.elapsed() > MESSAGE_DEADLINE) {
+            self.discard(self.buf.len());
+            return Err(invalid("message did not arrive in time"));
+        }
+
+        return Ok(None);
+    }
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    return e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut;
+}
+
+/**
+ * A new connection whose handshake is still on its way. Servers keep these
+ * around and poll them with everything else, so a peer that never sends its
+ * handshake cannot hold anyone up.
+ */
+pub struct PendingHandshake<S> {
+    stream: S,
+    reader: JsonReader,
+    since: Instant,
+}
+
+impl<S: Read> PendingHandshake<S> {
+    pub fn new(stream: S) -> Self {
+        return PendingHandshake {
+            stream,
+            reader: JsonReader::default(),
+            since: Instant::now(),
+        };
+    }
+
+    /**
+     * Return the handshake once it has arrived completely. Fails if it is
+     * invalid or has not arrived within [RESPONSE_TIMEOUT].
+     */
+    pub fn poll<T: DeserializeOwned>(&mut self) -> io::Result<Option<T>> {
+        if let Some(handshake) = self.reader.poll(&mut self.stream)? {
+            return Ok(Some(handshake));
+        }
+        if self.since.elapsed() > RESPONSE_TIMEOUT {
+            return Err(io::Error::new(
+                ErrorKind::TimedOut,
+                "the handshake did not arrive in time",
+            ));
+        }
+
+        return Ok(None);
+    }
+
+    /**
+     * The connection and its reader, which may hold what was sent after the
+     * handshake.
+     */
+    pub fn into_parts(self) -> (S, JsonReader) {
+        return (self.stream, self.reader);
+    }
+}
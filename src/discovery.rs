@@ -0,0 +1,171 @@
+use std::io::{self, ErrorKind};
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// UDP port games are advertised on
+pub const DISCOVERY_PORT: u16 = 41234;
+
+/// How often an open game is announced
+const ADVERTISE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Games not heard from for this long are dropped from the list
+const GAME_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// Marks our datagrams, anything else on the port is ignored
+const MAGIC: &str = "sieric-chess";
+
+/**
+ * What a host announces about its open game. There is no color or time
+ * control in it: the joining player picks the color in the handshake, and
+ * games are untimed.
+ */
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Advert {
+    game: String,
+    /// Name of the hosting player
+    pub name: String,
+    /// TCP port the game is hosted on
+    pub port: u16,
+}
+
+impl Advert {
+    pub fn new(name: &str, port: u16) -> Self {
+        return Advert {
+            game: String::from(MAGIC),
+            name: String::from(name),
+            port,
+        };
+    }
+
+    /**
+     * One line describing the game for the game list.
+     */
+    pub fn describe(&self) -> String {
+        return format!("{}, pick your color", self.name);
+    }
+}
+
+/**
+ * Broadcasts an [Advert] on the local network until dropped.
+ */
+pub struct Advertiser {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Advertiser {
+    pub fn start(advert: &Advert) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+        let bytes = serde_json::to_vec(advert)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let thread = thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                let target = (Ipv4Addr::BROADCAST, DISCOVERY_PORT);
+                if let Err(e) = socket.send_to(&bytes, target) {
+                    eprintln!("Could not advertise the game: {}", e);
+                    return;
+                }
+                thread::park_timeout(ADVERTISE_INTERVAL);
+            }
+        });
+
+        return Ok(Advertiser { stop, thread: Some(thread) });
+    }
+}
+
+impl Drop for Advertiser {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+/**
+ * An open game seen on the local network.
+ */
+pub struct LanGame {
+    /// Where to connect to
+    pub addr: SocketAddr,
+    pub advert: Advert,
+    last_seen: Instant,
+}
+
+/**
+ * Listens for [Advert]s and keeps a list of the open games.
+ */
+pub struct Browser {
+    socket: UdpSocket,
+    games: Vec<LanGame>,
+}
+
+impl Browser {
+    /**
+     * Start listening. Only one program on the machine can listen at a time,
+     * std cannot share the port.
+     */
+    pub fn new() -> io::Result<Self> {
+        let addr = (Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT);
+        let socket = UdpSocket::bind(addr).map_err(|e| match e.kind() {
+            ErrorKind::AddrInUse => io::Error::new(e.kind(), format!(
+                "UDP port {} is taken, another copy of the game on this \
+                 machine is probably browsing already", DISCOVERY_PORT,
+            )),
+            _ => e,
+        })?;
+        socket.set_nonblocking(true)?;
+        return Ok(Browser { socket, games: Vec::new() });
+    }
+
+    /**
+     * Read the adverts that arrived since the last call and forget games that
+     * went quiet.
+     */
+    pub fn poll(&mut self) {
+        let mut buf = [0u8; 1024];
+        loop {
+            let (n, from) = match self.socket.recv_from(&mut buf) {
+                Ok(packet) => packet,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    eprintln!("Could not read adverts: {}", e);
+                    break;
+                },
+            };
+
+            let advert: Advert = match serde_json::from_slice(&buf[..n]) {
+                Ok(advert) => advert,
+                Err(_) => continue,
+            };
+            if advert.game != MAGIC {
+                continue;
+            }
+
+            let addr = SocketAddr::new(from.ip(), advert.port);
+            let last_seen = Instant::now();
+            match self.games.iter_mut().find(|g| g.addr == addr) {
+                Some(game) => {
+                    game.advert = advert;
+                    game.last_seen = last_seen;
+                },
+                None => self.games.push(LanGame { addr, advert, last_seen }),
+            }
+        }
+
+        self.games.retain(|g| g.last_seen.elapsed() < GAME_TIMEOUT);
+    }
+
+    pub fn games(&self) -> &[LanGame] {
+        return &self.games;
+    }
+}
@@ -0,0 +1,226 @@
+use crate::animation::*;
+use crate::annotation::*;
+use crate::chess_engine::*;
+use crate::protocol::loc_name;
+
+fn name_at(board: &BoardSnapshot, loc: ChessLoc) -> &str {
+    return &board[(8*loc.1 + loc.0) as usize].1;
+}
+
+/**
+ * The moves the player to move has in `game`, as start and target squares.
+ */
+pub fn legal_moves(game: &mut dyn ChessGame) -> Vec<(ChessLoc, ChessLoc)> {
+    let mut out = Vec::new();
+    for y in 0..8 {
+        for x in 0..8 {
+            for to in game.get_moves(&(x, y)).into_keys() {
+                out.push(((x, y), to));
+            }
+        }
+    }
+    return out;
+}
+
+/**
+ * Whether the player to move is in check, as the engine tells it.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KingState {
+    Safe,
+    Check,
+    Mate,
+}
+
+impl KingState {
+    pub fn of(game: &mut dyn ChessGame) -> Self {
+        let player = game.get_player();
+        if !game.in_check(player) {
+            return KingState::Safe;
+        }
+        return match game.get_state() {
+            ChessState::JoeverWhite | ChessState::JoeverBlack
+                => KingState::Mate,
+            _ => KingState::Check,
+        };
+    }
+}
+
+/**
+ * What has to be added to the piece letter to tell the move apart from moves
+ * of the same kind of piece to the same square: the file, the rank or both.
+ * `before` is the position the move was made in.
+ */
+fn disambiguation(before: &HistoryEntry, from: ChessLoc, to: ChessLoc)
+    -> String {
+    let name = name_at(&before.board, from);
+    let rivals: Vec<ChessLoc> = before.moves.iter()
+        .filter(|&&(loc, target)| target == to && loc != from
+            && name_at(&before.board, loc) == name)
+        .map(|&(loc, _)| loc)
+        .collect();
+
+    let square = loc_name(&from);
+    if rivals.is_empty() {
+        return String::new();
+    } else if rivals.iter().all(|loc| loc.0 != from.0) {
+        return String::from(&square[..1]);
+    } else if rivals.iter().all(|loc| loc.1 != from.1) {
+        return String::from(&square[1..]);
+    }
+    return square;
+}
+
+/**
+ * Describe the move from `before` to `after` in standard algebraic notation
+ * (SAN), e.g. `Nf3`, `exd6`, `Rad1`, `e8=Q+` or `O-O`. `check` is what the
+ * move did to the opponent.
+ */
+fn describe_move(before: &HistoryEntry, after: &BoardSnapshot,
+                 check: KingState) -> String {
+    let (slides, fades) = diff(&before.board, after);
+
+    let castling = if slides.len() == 2 {
+        slides.iter().find(|s| s.piece == "K")
+    } else {
+        None
+    };
+
+    let mut out = String::new();
+    if let Some(king) = castling {
+        out += if king.to.0 > king.from.0 { "O-O" } else { "O-O-O" };
+    } else {
+        let slide = match slides.first() {
+            Some(slide) => slide,
+            None => return String::from("--"),
+        };
+
+        let capture = !fades.is_empty();
+        if slide.piece == "P" {
+            if capture {
+                out += &loc_name(&slide.from)[..1];
+            }
+        } else {
+            out += &slide.piece;
+            out += &disambiguation(before, slide.from, slide.to);
+        }
+        if capture {
+            out += "x";
+        }
+        out += &loc_name(&slide.to);
+
+        let promoted = name_at(after, slide.to);
+        if promoted != slide.piece {
+            out += &format!("={}", promoted);
+        }
+    }
+
+    out += match check {
+        KingState::Safe => "",
+        KingState::Check => "+",
+        KingState::Mate => "#",
+    };
+
+    return out;
+}
+
+pub struct HistoryEntry {
+    pub board: BoardSnapshot,
+    /// The move leading to this position, [None] for the starting position
+    pub notation: Option<String>,
+    pub annotations: Annotations,
+    /// The moves of the player to move, see [legal_moves]
+    moves: Vec<(ChessLoc, ChessLoc)>,
+}
+
+/**
+ * Every position of the current game, starting with the initial one.
+ */
+pub struct History {
+    entries: Vec<HistoryEntry>,
+}
+
+impl History {
+    /**
+     * Start with `board`, where the player to move has `moves`.
+     */
+    pub fn new(board: BoardSnapshot, moves: Vec<(ChessLoc, ChessLoc)>)
+        -> Self {
+        return History {
+            entries: vec![HistoryEntry {
+                board,
+                notation: None,
+                annotations: Annotations::default(),
+                moves,
+            }],
+        };
+    }
+
+    /**
+     * Add the position after a move, with the `moves` of the player to move
+     * next and whether the move gave `check`, both from the engine.
+     */
+    pub fn push(&mut self, board: BoardSnapshot,
+                moves: Vec<(ChessLoc, ChessLoc)>, check: KingState) {
+        let before = self.entries.last().unwrap();
+        let notation = describe_move(before, &board, check);
+        self.entries.push(HistoryEntry {
+            board,
+            notation: Some(notation),
+            annotations: Annotations::default(),
+            moves,
+        });
+    }
+
+    /**
+     * Index of the current position. Ply `0` is the starting position.
+     */
+    pub fn last_ply(&self) -> usize {
+        return self.entries.len() - 1;
+    }
+
+    pub fn get(&self, ply: usize) -> &HistoryEntry {
+        return &self.entries[ply];
+    }
+
+    pub fn get_mut(&mut self, ply: usize) -> &mut HistoryEntry {
+        return &mut self.entries[ply];
+    }
+
+    /**
+     * Export the game as PGN, with the moves in SAN and the annotations of
+     * each position as `[%cal]`/`[%csl]` comments. `result` is the PGN result
+     * tag, e.g. `1-0` or `*`.
+     */
+    pub fn to_pgn(&self, result: &str) -> String {
+        let mut out = format!("[Event \"?\"]
+[Site \"?\"]
+[Date \"????.??.??\"]
+[Round \"?\"]
+[White \"?\"]
+[Black \"?\"]
+[Result \"{}\"]
+
+", result);
+
+        for (ply, entry) in self.entries.iter().enumerate() {
+            if let Some(notation) = &entry.notation {
+                /* ply 1 is white's first move */
+                if ply % 2 == 1 {
+                    out += &format!("{}. ", ply / 2 + 1);
+                }
+                out += notation;
+                out += " ";
+            }
+
+            if !entry.annotations.is_empty() {
+                out += &format!("{{{}}} ", entry.annotations.to_pgn());
+            }
+        }
+
+        out += result;
+        out += "\n";
+
+        return out;
+    }
+}
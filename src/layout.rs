@@ -0,0 +1,62 @@
+use ggez::Context;
+use ggez::graphics::Rect;
+
+/// Share of the window width kept free for the side panel
+const PANEL_FRACTION: f32 = 0.25;
+
+/// Space around the board as a share of the smaller window dimension
+const MARGIN_FRACTION: f32 = 0.03;
+
+/**
+ * Factor to multiply text sizes (in logical pixels) with, so text has the same
+ * physical size on high DPI screens.
+ */
+pub fn ui_scale(ctx: &Context) -> f32 {
+    return ctx.gfx.window().scale_factor() as f32;
+}
+
+/**
+ * Where things go on the in-game screen. The board is always square and sits
+ * to the left, with the side panel filling the rest of the window.
+ */
+pub struct Layout {
+    pub board: Rect,
+    pub panel: Rect,
+    /// Side length of one square
+    pub square: f32,
+    pub ui_scale: f32,
+}
+
+impl Layout {
+    pub fn new(ctx: &Context) -> Self {
+        let (win_w, win_h) = ctx.gfx.drawable_size();
+        let margin = win_w.min(win_h) * MARGIN_FRACTION;
+
+        let size = (win_h - 2.*margin)
+            .min(win_w * (1. - PANEL_FRACTION) - 2.*margin)
+            .max(8.);
+        let board = Rect::new(margin, (win_h - size) / 2., size, size);
+
+        let panel_x = board.right() + margin;
+        let panel = Rect::new(
+            panel_x,
+            margin,
+            (win_w - panel_x - margin).max(0.),
+            (win_h - 2.*margin).max(0.),
+        );
+
+        return Layout {
+            board,
+            panel,
+            square: size / 8.,
+            ui_scale: ui_scale(ctx),
+        };
+    }
+
+    /**
+     * Scale a text size given in logical pixels to the screen.
+     */
+    pub fn text(&self, size: f32) -> f32 {
+        return size * self.ui_scale;
+    }
+}
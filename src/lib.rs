@@ -0,0 +1,18 @@
+/*!
+ * Chess engines and networking, shared by the game and the tests. Everything
+ * drawing to the screen lives in the binary.
+ */
+
+pub mod ai_engine;
+pub mod chess_engine;
+pub mod connection;
+pub mod discovery;
+pub mod local_engine;
+pub mod pipe;
+pub mod protocol;
+pub mod recorder;
+pub mod remote_engine;
+pub mod remote_host_engine;
+pub mod script;
+pub mod spectators;
+pub mod transport;
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::fmt;
 
 use chess::*;
 use crate::chess_engine::*;
@@ -14,31 +15,360 @@ fn to_chess_move(mv: &Move) -> ChessMove {
     };
 }
 
+/**
+ * Special moves that can be turned off, e.g. for teaching games.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Rules {
+    pub castling: bool,
+    pub en_passant: bool,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        return Rules { castling: true, en_passant: true };
+    }
+}
+
+impl Rules {
+    /**
+     * One line per rule, for [ChessGame::rules].
+     */
+    pub fn describe(&self) -> Vec<String> {
+        let on = |allowed: bool| if allowed { "yes" } else { "no" };
+        return vec![
+            format!("Castling: {}", on(self.castling)),
+            format!("En passant: {}", on(self.en_passant)),
+        ];
+    }
+}
+
+/**
+ * Why a move was refused. The message shown for each is meant for the player
+ * who tried the move.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IllegalMove {
+    /// The game ended before the move
+    GameOver,
+    /// It is the other player's turn
+    NotYourTurn,
+    /// A square of the move is not on the board
+    OffBoard,
+    /// The move starts on a square without one of the player's pieces
+    NoPiece,
+    /// The piece does not move like that
+    IllegalForPiece,
+    /// The move would leave the player's own king attacked
+    LeavesKingInCheck,
+    /// The promotion piece does not fit the move
+    BadPromotion,
+    /// Castling is turned off by the [Rules]
+    CastlingDisabled,
+    /// The king is in check or would pass or land on an attacked square
+    CastlingThroughCheck,
+    /// The king or rook moved before, or something is in the way
+    CannotCastle,
+    /// En passant is turned off by the [Rules]
+    EnPassantDisabled,
+    /// The pawn next to it did not just move two squares
+    NoEnPassant,
+}
+
+impl fmt::Display for IllegalMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            IllegalMove::GameOver => "the game is over",
+            IllegalMove::NotYourTurn => "it is not your turn",
+            IllegalMove::OffBoard => "that move is off the board",
+            IllegalMove::NoPiece => "none of your pieces is on that square",
+            IllegalMove::IllegalForPiece => "that piece cannot move there",
+            IllegalMove::LeavesKingInCheck
+                => "that would leave your king in check",
+            IllegalMove::BadPromotion
+                => "that is not a valid promotion for this move",
+            IllegalMove::CastlingDisabled
+                => "castling is turned off in this game",
+            IllegalMove::CastlingThroughCheck
+                => "the king cannot castle out of, through or into check",
+            IllegalMove::CannotCastle => "castling needs the king and rook \
+                unmoved and nothing between them",
+            IllegalMove::EnPassantDisabled
+                => "en passant is turned off in this game",
+            IllegalMove::NoEnPassant => "en passant is only possible right \
+                after the other pawn moved two squares",
+        };
+        return write!(f, "{}", message);
+    }
+}
+
+fn on_board(loc: ChessLoc) -> bool {
+    return (0..8).contains(&loc.0) && (0..8).contains(&loc.1);
+}
+
+/**
+ * Return [true] if the piece called `name` of `white` (or black) moves in a
+ * way that takes it from `from` to `to`. `color_at` returns the color of the
+ * piece on a square ([true] for white), [None] if the square is empty.
+ * Whether the move leaves the king in check is not looked at, and neither are
+ * castling and en passant.
+ */
+fn could_reach(
+    color_at: impl Fn(ChessLoc) -> Option<bool>,
+    white: bool,
+    name: &str,
+    from: ChessLoc,
+    to: ChessLoc,
+) -> bool {
+    if from == to || !on_board(from) || !on_board(to) {
+        return false;
+    }
+    let target = color_at(to);
+    if target == Some(white) {
+        return false;
+    }
+
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let path_clear = || {
+        let steps = dx.abs().max(dy.abs());
+        return (1..steps).all(|i| {
+            color_at((from.0 + i*dx.signum(), from.1 + i*dy.signum()))
+                .is_none()
+        });
+    };
+    let straight = dx == 0 || dy == 0;
+    let diagonal = dx.abs() == dy.abs();
+
+    return match name {
+        "K" => dx.abs() <= 1 && dy.abs() <= 1,
+        "Q" => (straight || diagonal) && path_clear(),
+        "R" => straight && path_clear(),
+        "B" => diagonal && path_clear(),
+        "N" => dx.abs() * dy.abs() == 2,
+        "P" => {
+            let (forward, start) = if white { (1, 1) } else { (-1, 6) };
+            if target.is_some() {
+                dx.abs() == 1 && dy == forward
+            } else {
+                dx == 0 && (dy == forward
+                    || dy == 2*forward && from.1 == start && path_clear())
+            }
+        },
+        _ => false,
+    };
+}
+
 pub struct LocalGame {
     game: Game,
+    rules: Rules,
 }
 
 impl LocalGame {
     pub fn new() -> Self {
+        return LocalGame::with_rules(Rules::default());
+    }
+
+    pub fn with_rules(rules: Rules) -> Self {
         return LocalGame {
             game: Game::new(),
+            rules,
         };
     }
 
+    pub fn get_rules(&self) -> Rules {
+        return self.rules;
+    }
+
+    fn piece_at(&self, loc: ChessLoc) -> Option<Piece> {
+        return match self.game.board().at(Loc { x: loc.0, y: loc.1 }) {
+            Square::Occupied(piece) => Some(piece),
+            _ => None,
+        };
+    }
+
+    fn color_at(&self, loc: ChessLoc) -> Option<bool> {
+        return self.piece_at(loc).map(|piece| piece.is_player(Player::White));
+    }
+
+    /**
+     * Return [true] if a piece of `by_white` (or black) attacks `loc`.
+     */
+    fn attacked(&self, loc: ChessLoc, by_white: bool) -> bool {
+        /* pawns only attack where there is something to capture */
+        let color_at = |sq: ChessLoc| if sq == loc {
+            Some(!by_white)
+        } else {
+            self.color_at(sq)
+        };
+
+        for y in 0..8 {
+            for x in 0..8 {
+                let piece = match self.piece_at((x, y)) {
+                    Some(piece) => piece,
+                    None => continue,
+                };
+                let white = piece.is_player(Player::White);
+                if white == by_white
+                    && could_reach(color_at, white, piece.kind.name,
+                                   (x, y), loc) {
+                    return true;
+                }
+            }
+        }
+
+        return false;
+    }
+
+    /**
+     * Explain why the king of `white` (or black) cannot castle from `from`
+     * to `to`.
+     */
+    fn why_no_castling(&self, white: bool, from: ChessLoc, to: ChessLoc)
+        -> IllegalMove {
+        if !self.rules.castling {
+            return IllegalMove::CastlingDisabled;
+        }
+
+        let passed = [from, ((from.0 + to.0) / 2, from.1), to];
+        if passed.iter().any(|&loc| self.attacked(loc, !white)) {
+            return IllegalMove::CastlingThroughCheck;
+        }
+        return IllegalMove::CannotCastle;
+    }
+
+    /**
+     * Explain why the player to move cannot move from `from` to `to`, for a
+     * move that is not among the legal ones. Turn and promotion are up to the
+     * caller.
+     */
+    pub fn why_illegal(&self, from: ChessLoc, to: ChessLoc) -> IllegalMove {
+        if !on_board(from) {
+            return IllegalMove::NoPiece;
+        }
+        let piece = match self.piece_at(from) {
+            Some(piece) if piece.is_player(self.game.player()) => piece,
+            _ => return IllegalMove::NoPiece,
+        };
+        if !on_board(to) {
+            return IllegalMove::IllegalForPiece;
+        }
+
+        let white = piece.is_player(Player::White);
+        let home = if white { 0 } else { 7 };
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let castling = piece.kind.name == "K"
+            && from == (4, home) && dy == 0 && dx.abs() == 2;
+        if castling {
+            return self.why_no_castling(white, from, to);
+        }
+
+        /* a pawn moving diagonally onto an empty square takes en passant */
+        let forward = if white { 1 } else { -1 };
+        let en_passant = piece.kind.name == "P"
+            && dx.abs() == 1 && dy == forward
+            && self.piece_at(to).is_none();
+        if en_passant {
+            let beside = self.piece_at((to.0, from.1));
+            let pawn_beside = beside.is_some_and(|other| {
+                other.kind.name == "P"
+                    && other.is_player(Player::White) != white
+            });
+            return if !pawn_beside {
+                IllegalMove::IllegalForPiece
+            } else if !self.rules.en_passant {
+                IllegalMove::EnPassantDisabled
+            } else {
+                IllegalMove::NoEnPassant
+            };
+        }
+
+        let color_at = |loc: ChessLoc| self.color_at(loc);
+        if !could_reach(color_at, white, piece.kind.name, from, to) {
+            return IllegalMove::IllegalForPiece;
+        }
+        return IllegalMove::LeavesKingInCheck;
+    }
+
+    /**
+     * Return [false] if `mv` is a special move turned off by the rules.
+     */
+    fn allowed(&self, mv: &Move) -> bool {
+        let (from, to) = (&mv.from, &mv.to);
+        let piece = match self.game.board().at(Loc { x: from.x, y: from.y }) {
+            Square::Occupied(piece) => piece.kind.name,
+            _ => return true,
+        };
+
+        if piece == "K" && (to.x - from.x).abs() == 2 {
+            return self.rules.castling;
+        }
+        /* only en passant captures onto an empty square */
+        let target = self.game.board().at(Loc { x: to.x, y: to.y });
+        if piece == "P" && to.x != from.x && matches!(target, Square::Empty) {
+            return self.rules.en_passant;
+        }
+
+        return true;
+    }
+
+    fn legal_moves(&self, from: Option<Loc>, to: Option<Loc>) -> Vec<Move> {
+        let mut moves = self.game.get_moves(from, to);
+        moves.retain(|mv| self.allowed(mv));
+        return moves;
+    }
+
     pub fn get_all_moves(&self) -> Vec<ChessMove> {
-        let moves = self.game.get_moves(None, None);
+        let moves = self.legal_moves(None, None);
         return moves.iter().map(to_chess_move).collect();
     }
 
+    /**
+     * All legal moves, each with the name of the piece it promotes to. There
+     * is one move for every piece a pawn can promote to.
+     */
+    pub fn get_all_promotions(&self) -> Vec<(ChessMove, Option<&'static str>)> {
+        let moves = self.legal_moves(None, None);
+        return moves.iter()
+            .map(|mv| (to_chess_move(mv), mv.is_promotion().map(|k| k.name)))
+            .collect();
+    }
+
+    /**
+     * Like [ChessGame::apply_move], but only plays the move if it promotes to
+     * exactly the piece named `promotion` ("Q", "R", "B" or "N"), or does not
+     * promote if it is [None].
+     */
+    pub fn apply_promotion(&mut self, mv: &ChessMove, promotion: Option<&str>)
+        -> bool {
+        let from = Loc { x: mv.from.0, y: mv.from.1 };
+        let to = Loc { x: mv.to.0, y: mv.to.1 };
+
+        let moves = self.legal_moves(Some(from), Some(to));
+        let mv = moves.iter()
+            .find(|m| m.is_promotion().map(|kind| kind.name) == promotion);
+
+        if let Some(mv) = mv {
+            self.game.play_move(mv);
+            return true;
+        }
+        return false;
+    }
+
     pub fn get_board(&self) -> [Square; 8*8] {
         return self.game.board().squares;
     }
 }
 
+impl Default for LocalGame {
+    fn default() -> Self {
+        return LocalGame::new();
+    }
+}
+
 impl ChessGame for LocalGame {
     fn get_moves(&mut self, loc: &ChessLoc) -> HashMap<ChessLoc, ChessMove> {
         let loc2 = Loc { x: loc.0, y: loc.1 };
-        let moves = self.game.get_moves(Some(loc2), None);
+        let moves = self.legal_moves(Some(loc2), None);
 
         let mut map = HashMap::new();
         for mv in moves {
@@ -55,21 +385,9 @@ impl ChessGame for LocalGame {
     }
 
     fn apply_move(&mut self, mv2: &ChessMove) -> bool {
-        let from = Loc { x: mv2.from.0, y: mv2.from.1 };
-        let to = Loc { x: mv2.to.0, y: mv2.to.1 };
-
-        /* why */
-        let moves = self.game.get_moves(Some(from), Some(to));
-        let mv = moves.iter().filter(|m| match m.is_promotion() {
-            Some(kind) => kind.name == "Q",
-            _ => true,
-        }).next();
-
-        if let Some(mv) = mv {
-            self.game.play_move(&mv);
-            return true;
-        }
-        return false;
+        /* the board only offers promotions to a queen */
+        return self.apply_promotion(mv2, None)
+            || self.apply_promotion(mv2, Some("Q"));
     }
 
     fn get_piece(&mut self, loc: &ChessLoc) -> (bool, String) {
@@ -86,7 +404,26 @@ impl ChessGame for LocalGame {
         return self.game.player() == Player::White;
     }
 
+    fn rules(&self) -> Vec<String> {
+        return self.rules.describe();
+    }
+
     fn get_state(&mut self) -> ChessState {
+        /* the engine does not know that the special moves might be off, which
+         * can leave a player without moves it considers legal */
+        let stuck = matches!(self.game.state(), State::Playing)
+            && self.legal_moves(None, None).is_empty();
+        if stuck {
+            let white = self.get_player();
+            return if !self.in_check(white) {
+                ChessState::JoeverDraw
+            } else if white {
+                ChessState::JoeverBlack
+            } else {
+                ChessState::JoeverWhite
+            };
+        }
+
         return match self.game.state() {
             State::Playing => ChessState::Ongoing,
             State::Checkmate => if self.get_player() {
@@ -1,58 +1,696 @@
-mod chess_engine;
-mod local_engine;
-mod remote_engine;
-mod remote_host_engine;
-
+mod animation;
+mod annotation;
+mod cli;
+mod history;
+mod layout;
+mod notification;
+mod settings;
+mod sound;
+mod text_input;
+mod theme;
+
+/* the engines live in the library so the tests can use them */
+use sieric_chess_gui::{
+    ai_engine,
+    chess_engine,
+    discovery,
+    local_engine,
+    protocol,
+    recorder,
+    remote_engine,
+    remote_host_engine,
+    transport,
+};
+
+use ai_engine::AiGame;
+use animation::*;
+use annotation::*;
 use chess_engine::*;
-use local_engine::LocalGame;
-use remote_engine::RemoteGame;
-use remote_host_engine::RemoteHostGame;
-
-use std::{collections::HashMap, env, path};
-
+use cli::{Args, Launch};
+use discovery::{Advert, Advertiser, Browser};
+use history::{History, KingState, legal_moves};
+use layout::*;
+use local_engine::{LocalGame, Rules};
+use notification::Notifications;
+use recorder::{Recorder, RecordingAcceptor, RecordingConnector};
+use remote_engine::{RemoteGame, Verification};
+use remote_host_engine::WaitingHost;
+use settings::Settings;
+use sound::*;
+use text_input::TextInput;
+use theme::*;
+use transport::*;
+
+use std::{collections::HashMap, env, fs, path, time};
+use std::net::{SocketAddr, ToSocketAddrs};
+
+use chess_network_protocol::Color;
 use ggez::{self, event, GameResult, GameError, Context};
 use ggez::winit::event::VirtualKeyCode;
+use ggez::input::keyboard::KeyInput;
 use ggez::glam::*;
-use ggez::audio::{self, SoundSource};
 use ggez::graphics::{self, Rect, DrawParam};
 use ggez::conf::{WindowMode, WindowSetup};
 
+/// Text sizes in logical pixels, see [Layout::text]
+const MENU_TEXT_SIZE: f32 = 40.;
+const PANEL_TEXT_SIZE: f32 = 22.;
+const BANNER_TEXT_SIZE: f32 = 80.;
+
+/// Longest frame time fed to animations, so a blocking [ChessGame::wait_move]
+/// does not skip the opponent's animation entirely
+const MAX_ANIM_STEP: f32 = 1. / 30.;
+
+/// Prefix of `--host` and `--join` values that name a Unix socket
+#[cfg(unix)]
+const UNIX_PREFIX: &str = "unix:";
+
 #[allow(dead_code)]
 enum GameState {
     Init,
     Hosting,
+    WaitingForClient,
     Joining,
     InGame,
+    Settings,
+    Spectating,
+}
+
+/// Returns the hosted game once its client connected
+type ClientPoll<'a>
+    = Box<dyn FnMut() -> std::io::Result<Option<Box<dyn ChessGame + 'a>>> + 'a>;
+
+/**
+ * A hosted game waiting for its client, see [MainState::host_game]. The game
+ * is announced on the local network until this is dropped.
+ */
+struct WaitingForClient<'a> {
+    poll: ClientPoll<'a>,
+    _advertiser: Option<Advertiser>,
+    /// Where the client can connect
+    address: String,
+}
+
+/**
+ * Parse a port number typed by the user.
+ */
+fn parse_port(port: &str) -> Result<u16, String> {
+    return match port.trim().parse::<u16>() {
+        Ok(port) if port != 0 => Ok(port),
+        _ => Err(format!("Invalid port: {}", port.trim())),
+    };
+}
+
+/**
+ * Turn the address and port fields into a socket address, resolving host names.
+ * If the port field is empty the address may contain the port instead, as in
+ * `example.com:1234` or `[::1]:1234`.
+ */
+fn resolve_address(host: &str, port: &str) -> Result<SocketAddr, String> {
+    let host = host.trim();
+    if host.is_empty() {
+        return Err(String::from("Enter an address"));
+    }
+
+    if port.trim().is_empty() {
+        if let Ok(addr) = host.parse::<SocketAddr>() {
+            return Ok(addr);
+        }
+        /* a single colon can only separate a host name from its port */
+        if host.matches(':').count() == 1 {
+            let (host, port) = host.split_once(':').unwrap();
+            return resolve_address(host, port);
+        }
+    }
+
+    let port = parse_port(port)?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    let mut addrs = (host, port).to_socket_addrs()
+        .map_err(|e| format!("Could not resolve {}: {}", host, e))?;
+    return addrs.next()
+        .ok_or_else(|| format!("No address found for {}", host));
+}
+
+fn verification_name(verification: Verification) -> &'static str {
+    return match verification {
+        Verification::Off => "off",
+        Verification::Warn => "warn",
+        Verification::Abort => "abort game",
+    };
 }
 
 struct MainState<'a> {
     state: GameState,
     game: Option<Box<dyn ChessGame + 'a>>,
-    buf: String,
-    music: audio::Source,
+    inputs: Vec<TextInput>,
+    /// Index of the focused field in `inputs`
+    focus: usize,
+    input_error: Option<String>,
+    /// Set while the text typed by the key that opened a form is still to
+    /// come, ggez sends it after the key press
+    skip_text: bool,
+    notifications: Notifications,
+    /// Open games on the local network, only listened for while joining
+    lan: Option<Browser>,
+    /// Why the open games cannot be listed, shown while joining
+    lan_error: Option<String>,
+    /// Index of the LAN game chosen with the arrow keys
+    lan_selected: Option<usize>,
+    music: Music,
+    sounds: Sounds,
+    settings: Settings,
+    config_dir: path::PathBuf,
     selected: Option<IVec2>,
     moves: HashMap<ChessLoc, ChessMove>,
+    hovered: Option<ChessLoc>,
+    hover_moves: HashMap<ChessLoc, ChessMove>,
     turn: usize,
     can_wait: bool,
-    flip_mode: bool,
+    board: BoardSnapshot,
+    animation: Option<Animation>,
+    pieces: PieceSet,
+    history: History,
+    /// The ply being reviewed, [None] when following the game live
+    view: Option<usize>,
+    /// Square and color where the current right-drag started
+    annotation_start: Option<(ChessLoc, AnnotationColor)>,
+    /// Show the game's rules in the side panel instead of the moves
+    show_info: bool,
+    /// Rules for games hosted from the menu
+    host_rules: Rules,
+    /// The hosted game while its client has not connected yet
+    waiting: Option<WaitingForClient<'a>>,
+    /// Records the messages of online games, see `--record`
+    recorder: Option<Recorder>,
+}
+
+fn draw_arrow(
+    ctx: &mut Context,
+    canvas: &mut graphics::Canvas,
+    from: Vec2,
+    to: Vec2,
+    width: f32,
+    color: graphics::Color,
+) -> GameResult {
+    let dir = (to - from).normalize_or_zero();
+    let normal = Vec2::new(-dir.y, dir.x);
+    let head_len = width * 2.5;
+    let base = to - dir * head_len;
+
+    canvas.draw(
+        &graphics::Mesh::new_line(ctx, &[from, base], width, color)?,
+        Vec2::ZERO,
+    );
+    canvas.draw(
+        &graphics::Mesh::new_polygon(
+            ctx,
+            graphics::DrawMode::fill(),
+            &[
+                to,
+                base + normal * head_len * 0.6,
+                base - normal * head_len * 0.6,
+            ],
+            color,
+        )?,
+        Vec2::ZERO,
+    );
+
+    return Ok(());
+}
+
+fn draw_piece(
+    canvas: &mut graphics::Canvas,
+    pieces: &PieceSet,
+    piece: &str,
+    white: bool,
+    pos: Vec2,
+    size: f32,
+    alpha: f32,
+) {
+    if let Some(image) = pieces.image(white, piece) {
+        canvas.draw(
+            image,
+            DrawParam::default()
+                .dest(pos)
+                .scale(Vec2::splat(size / image.width() as f32))
+                .color(graphics::Color::new(1., 1., 1., alpha)),
+        );
+        return;
+    }
+
+    canvas.draw(
+        graphics::Text::new(piece)
+            .set_scale(size),
+        DrawParam::default()
+            .dest(pos)
+            .color(if white {
+                graphics::Color::from([1., 1., 1., alpha])
+            } else {
+                graphics::Color::from([0., 0., 0., alpha])
+            }),
+    );
 }
 
 impl<'a> MainState<'a> {
     fn new(ctx: &mut Context) -> GameResult<MainState<'a>> {
+        let config_dir = ctx.fs.user_config_dir().to_path_buf();
+        let settings = Settings::load(&config_dir);
+
+        let music = Music::new(ctx, settings.music_path.as_deref(),
+                               settings.music_volume, settings.music_muted);
+        let pieces = PieceSet::load(ctx, &settings.piece_set);
+
         return Ok(MainState {
             state: GameState::Init,
             game: None,
-            buf: String::new(),
-            music: audio::Source::new(ctx, "/copyright_infringement.flac")?,
+            inputs: Vec::new(),
+            focus: 0,
+            input_error: None,
+            skip_text: false,
+            notifications: Notifications::default(),
+            lan: None,
+            lan_error: None,
+            lan_selected: None,
+            music,
+            sounds: Sounds::new(ctx, settings.effects_volume,
+                                settings.effects_muted),
+            settings,
+            config_dir,
             selected: None,
             moves: HashMap::new(),
+            hovered: None,
+            hover_moves: HashMap::new(),
             turn: 0,
             can_wait: false,
-            flip_mode: false,
+            board: Vec::new(),
+            animation: None,
+            pieces,
+            history: History::new(Vec::new(), Vec::new()),
+            view: None,
+            annotation_start: None,
+            show_info: false,
+            host_rules: Rules::default(),
+            waiting: None,
+            recorder: None,
+        });
+    }
+
+    /**
+     * Go straight to the mode requested on the command line.
+     */
+    fn launch(&mut self, args: &Args) -> GameResult {
+        if let Some(path) = &args.record {
+            let recorder = Recorder::create(path).map_err(|e| {
+                GameError::CustomError(format!("Could not record to {}: {}",
+                                               path, e))
+            })?;
+            self.recorder = Some(recorder);
+        }
+
+        match &args.launch {
+            Launch::Menu | Launch::Help => (),
+            Launch::Local => match args.ai_depth {
+                Some(depth) => {
+                    let human_white = args.color == Color::White;
+                    self.start_game(Box::new(AiGame::new(human_white, depth)));
+                },
+                None => self.start_game(Box::new(LocalGame::new())),
+            },
+            #[cfg(unix)]
+            Launch::Host(path) if path.starts_with(UNIX_PREFIX) => {
+                let path = &path[UNIX_PREFIX.len()..];
+                self.host_game(UnixAcceptor::bind(path), None, None,
+                               self.host_rules);
+            },
+            Launch::Host(port) => {
+                let port = parse_port(port).map_err(GameError::CustomError)?;
+                let spectators = self.spectator_acceptor(port);
+                let advert = Some(self.lan_advert(port));
+                self.host_game(TcpAcceptor::bind(port), spectators, advert,
+                               self.host_rules);
+            },
+            Launch::Spectate(addr) => {
+                let addr = resolve_address(addr, "")
+                    .map_err(GameError::CustomError)?;
+                self.spectate_game(TcpConnector::new(addr));
+            },
+            #[cfg(unix)]
+            Launch::Join(path) if path.starts_with(UNIX_PREFIX) => {
+                let path = &path[UNIX_PREFIX.len()..];
+                self.join_game(UnixConnector::new(path), args.color);
+            },
+            Launch::Join(addr) => {
+                let addr = resolve_address(addr, "")
+                    .map_err(GameError::CustomError)?;
+                self.join_game(TcpConnector::new(addr), args.color);
+            },
+        }
+
+        return Ok(());
+    }
+
+    /**
+     * Connect to a server and start the game. Returns [false] and shows the
+     * error if the connection fails, leaving the menus as they are.
+     */
+    fn join_game<C: Connector + 'a>(&mut self, connector: C, color: Color)
+        -> bool {
+        let name = connector.describe();
+        let connector = RecordingConnector::new(connector,
+                                                self.recorder.clone());
+        return match RemoteGame::new(connector, color) {
+            Ok(mut game) => {
+                game.verify(self.settings.verification);
+                self.start_game(Box::new(game));
+                true
+            },
+            Err(e) => {
+                self.notifications
+                    .error(format!("Could not connect to {}: {}", name, e));
+                false
+            },
+        };
+    }
+
+    /**
+     * Watch the game behind `connector`, see [MainState::join_game].
+     */
+    fn spectate_game<C: Connector + 'a>(&mut self, connector: C) -> bool {
+        let name = connector.describe();
+        let connector = RecordingConnector::new(connector,
+                                                self.recorder.clone());
+        return match RemoteGame::spectate(connector) {
+            Ok(mut game) => {
+                game.verify(self.settings.verification);
+                self.start_game(Box::new(game));
+                true
+            },
+            Err(e) => {
+                self.notifications
+                    .error(format!("Could not watch {}: {}", name, e));
+                false
+            },
+        };
+    }
+
+    /**
+     * Listen for spectators on the port after the game's `port`. Hosting
+     * works without them, so failing to do so is only shown.
+     */
+    fn spectator_acceptor(&mut self, port: u16) -> Option<TcpAcceptor> {
+        let result = match port.checked_add(1) {
+            Some(port) => TcpAcceptor::bind(port),
+            None => return None,
+        };
+
+        return match result {
+            Ok(acceptor) => Some(acceptor),
+            Err(e) => {
+                self.notifications
+                    .error(format!("Spectators cannot connect: {}", e));
+                None
+            },
+        };
+    }
+
+    /**
+     * Wait for a client from [MainState::update], which starts the game
+     * once it connected. Returns [false] and shows the error if `acceptor`
+     * could not be set up. Spectators connect through `spectators` if given.
+     * `advert` is broadcast on the local network while waiting. The game is
+     * played by `rules`.
+     */
+    fn host_game<A: Acceptor + 'a>(
+        &mut self,
+        acceptor: std::io::Result<A>,
+        spectators: Option<A>,
+        advert: Option<Advert>,
+        rules: Rules,
+    ) -> bool {
+        let acceptor = match acceptor {
+            Ok(acceptor) => acceptor,
+            Err(e) => {
+                self.notifications
+                    .error(format!("Could not host the game: {}", e));
+                return false;
+            },
+        };
+        let advertiser = advert.and_then(|advert| {
+            Advertiser::start(&advert)
+                .map_err(|e| eprintln!("Could not advertise the game: {}", e))
+                .ok()
+        });
+
+        let recorder = &self.recorder;
+        let mut spectators = spectators
+            .map(|spectators| RecordingAcceptor::new(spectators,
+                                                     recorder.clone()));
+        let acceptor = RecordingAcceptor::new(acceptor, recorder.clone());
+        let address = acceptor.describe();
+        println!("Waiting for a client on {}", address);
+        if let Some(spectators) = &spectators {
+            println!("Spectators can connect on {}", spectators.describe());
+        }
+
+        let mut host = WaitingHost::new(acceptor, rules);
+        let poll: ClientPoll<'a> = Box::new(move || {
+            let game = host.poll()?.map(|mut game| {
+                if let Some(spectators) = spectators.take() {
+                    game.allow_spectators(spectators);
+                }
+                Box::new(game) as Box<dyn ChessGame + 'a>
+            });
+            Ok(game)
+        });
+        self.waiting = Some(WaitingForClient {
+            poll,
+            _advertiser: advertiser,
+            address,
         });
+        self.open_form(GameState::WaitingForClient, Vec::new());
+        return true;
+    }
+
+    /**
+     * Start the hosted game once its client connected.
+     */
+    fn poll_client(&mut self) {
+        let waiting = match self.waiting.as_mut() {
+            Some(waiting) => waiting,
+            None => return,
+        };
+
+        match (waiting.poll)() {
+            Ok(Some(game)) => {
+                self.waiting = None;
+                self.start_game(game);
+            },
+            Ok(None) => (),
+            Err(e) => {
+                self.notifications
+                    .error(format!("Could not host the game: {}", e));
+                self.cancel_hosting();
+            },
+        }
+    }
+
+    /**
+     * Stop waiting for a client and go back to the hosting form.
+     */
+    fn cancel_hosting(&mut self) {
+        self.waiting = None;
+        let port = self.default_port_text();
+        self.open_form(GameState::Hosting, vec![TextInput::new(&port)]);
+    }
+
+    /**
+     * Show what the game has to tell the user.
+     */
+    fn check_game(&mut self) {
+        if let Some(game) = self.game.as_deref_mut() {
+            while let Some(message) = game.take_message() {
+                self.notifications.error(message);
+            }
+        }
+    }
+
+    /**
+     * Connect to the opponent again and pick up the game where the server
+     * is.
+     */
+    fn reconnect(&mut self, ctx: &mut Context) {
+        let game = self.game.as_deref_mut().unwrap();
+        if game.is_connected() {
+            return;
+        }
+
+        match game.reconnect() {
+            Ok(waiting) => {
+                /* odd turns are the opponent's */
+                if waiting != (self.turn % 2 == 1) {
+                    self.turn += 1;
+                }
+                self.selected = None;
+                self.moves = HashMap::new();
+                self.view = None;
+                self.notifications.info("Reconnected");
+                self.board_changed(ctx);
+            },
+            Err(e) => self.notifications
+                .error(format!("Could not reconnect: {}", e)),
+        }
+    }
+
+    /**
+     * What to tell the local network about a game hosted on `port`.
+     */
+    fn lan_advert(&self, port: u16) -> Advert {
+        let name = if self.settings.player_name.is_empty() {
+            "Unnamed player"
+        } else {
+            self.settings.player_name.as_str()
+        };
+        return Advert::new(name, port);
+    }
+
+    fn start_game(&mut self, game: Box<dyn ChessGame + 'a>) {
+        self.lan = None;
+        self.game = Some(game);
+        let game = self.game.as_deref_mut().unwrap();
+        self.board = snapshot(game);
+        self.animation = None;
+        self.history = History::new(self.board.clone(), legal_moves(game));
+        self.view = None;
+        self.turn = if self.game.as_ref().unwrap().opponent_starts() {
+            1
+        } else {
+            0
+        };
+        self.state = GameState::InGame;
+    }
+
+    /**
+     * Take a new snapshot of the board, animate the difference to the previous
+     * one and play the matching sound. Call this whenever a move has been
+     * made.
+     */
+    fn board_changed(&mut self, ctx: &mut Context) {
+        let game = self.game.as_deref_mut().unwrap();
+        let board = snapshot(game);
+        if board == self.board {
+            return;
+        }
+
+        let event = if !matches!(game.get_state(), ChessState::Ongoing) {
+            SoundEvent::GameEnd
+        } else if game.in_check(true) || game.in_check(false) {
+            SoundEvent::Check
+        } else {
+            classify_move(&self.board, &board)
+        };
+        self.sounds.play(ctx, event);
+
+        self.animation
+            = Animation::between(&self.board, &board,
+                                 self.settings.anim_duration);
+        let check = KingState::of(game);
+        self.history.push(board.clone(), legal_moves(game), check);
+        self.board = board;
+
+        self.hovered = None;
+        self.hover_moves = HashMap::new();
+    }
+
+    fn save_settings(&self) {
+        if let Err(e) = self.settings.save(&self.config_dir) {
+            eprintln!("Could not save settings: {}", e);
+        }
+    }
+
+    fn change_music_volume(&mut self, delta: f32) {
+        self.settings.music_volume
+            = (self.settings.music_volume + delta).clamp(0., 1.);
+        self.music.set_volume(self.settings.music_volume);
+        self.save_settings();
+    }
+
+    fn change_anim_duration(&mut self, delta: f32) {
+        self.settings.anim_duration
+            = (self.settings.anim_duration + delta).clamp(0., 2.);
+        self.save_settings();
+    }
+
+    fn change_effects_volume(&mut self, delta: f32) {
+        self.settings.effects_volume
+            = (self.settings.effects_volume + delta).clamp(0., 1.);
+        self.sounds.set_volume(self.settings.effects_volume);
+        self.save_settings();
     }
 
 /********************** GameState::InGame **********************/
+    fn shown_ply(&self) -> usize {
+        return self.view.unwrap_or(self.history.last_ply());
+    }
+
+    fn export_pgn(&mut self, ctx: &mut Context)
+        -> std::io::Result<path::PathBuf> {
+        let result = match self.game.as_mut().unwrap().get_state() {
+            ChessState::JoeverWhite => "1-0",
+            ChessState::JoeverBlack => "0-1",
+            ChessState::JoeverDraw => "1/2-1/2",
+            _ => "*",
+        };
+
+        let secs = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let dir = ctx.fs.user_data_dir();
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!("game-{}.pgn", secs));
+        fs::write(&path, self.history.to_pgn(result))?;
+
+        return Ok(path);
+    }
+
+    fn square_pos(&self, loc: &ChessLoc, layout: &Layout) -> Vec2 {
+        let row = if !self.game.as_ref().unwrap().get_player()
+            && self.settings.flip_mode {
+            loc.1
+        } else {
+            7 - loc.1
+        };
+
+        return Vec2::new(
+            layout.board.x + layout.square * loc.0 as f32,
+            layout.board.y + layout.square * row as f32,
+        );
+    }
+
+    /**
+     * Inverse of [MainState::square_pos]. Returns [None] if the position is
+     * outside the board.
+     */
+    fn square_at(&self, x: f32, y: f32, layout: &Layout)
+        -> Option<ChessLoc> {
+        let file = ((x - layout.board.x) / layout.square).floor() as i32;
+        let row = ((y - layout.board.y) / layout.square).floor() as i32;
+        if !(0..8).contains(&file) || !(0..8).contains(&row) {
+            return None;
+        }
+
+        let rank = if !self.game.as_ref().unwrap().get_player()
+            && self.settings.flip_mode {
+            row
+        } else {
+            7 - row
+        };
+
+        return Some((file, rank));
+    }
+
     fn ingame_draw(&mut self, ctx: &mut Context) -> GameResult {
         let mut canvas = graphics::Canvas::from_frame(
             ctx,
@@ -60,70 +698,155 @@ impl<'a> MainState<'a> {
         );
 
         let (win_w, win_h) = ctx.gfx.drawable_size();
+        let layout = Layout::new(ctx);
+        let square = layout.square;
+        let theme = &THEMES[theme_index(&self.settings.theme)];
 
         for fake_i in 0..8 {
             for j in 0..8 {
                 let pos = Vec2::new(
-                    (win_w/8.) * j as f32,
-                    (win_h/8.) * fake_i as f32,
+                    layout.board.x + square * j as f32,
+                    layout.board.y + square * fake_i as f32,
                 );
                 let i = if !self.game.as_mut().unwrap().get_player()
-                    && self.flip_mode {
+                    && self.settings.flip_mode {
                     fake_i
                 } else {
                     7 - fake_i
                 };
 
-                let (piece_white, piece_text)
-                    = self.game.as_mut().unwrap().get_piece(&(j, i));
+                let (piece_white, piece_text) = &self.history
+                    .get(self.shown_ply()).board[(8*i + j) as usize];
 
                 canvas.draw(
                     &graphics::Mesh::new_rectangle(
                         ctx,
                         graphics::DrawMode::fill(),
-                        Rect::new(0., 0., win_w/8., win_h/8.),
+                        Rect::new(0., 0., square, square),
                         if (i + j) % 2 == 0 {
-                            graphics::Color::from([0.5, 0.5, 0.5, 1.])
+                            graphics::Color::from(theme.dark)
                         } else {
-                            graphics::Color::from([0.12, 0.4, 0., 1.])
+                            graphics::Color::from(theme.light)
                         }
                     )?,
                     pos,
                 );
 
-                match self.moves.get(&(j, i)) {
-                    Some(mv) => {
-                        canvas.draw(
-                            &graphics::Mesh::new_rectangle(
-                                ctx,
-                                graphics::DrawMode::fill(),
-                                Rect::new(0., 0., win_w/8., win_h/8.),
-                                if mv.capture {
-                                    graphics::Color::from([1., 0., 0., 0.5])
-                                } else {
-                                    graphics::Color::from([0., 0., 1., 0.5])
-                                },
-                            )?,
-                            pos,
-                        );
-                    },
-                    _ => (),
+                let highlight = if self.selected == Some(IVec2::new(j, i)) {
+                    Some(graphics::Color::from([1., 0.85, 0.2, 0.5]))
+                } else if self.selected.is_some()
+                    && self.hovered == Some((j, i))
+                    && self.moves.contains_key(&(j, i)) {
+                    Some(graphics::Color::from([1., 1., 1., 0.25]))
+                } else {
+                    None
+                };
+                if let Some(color) = highlight {
+                    canvas.draw(
+                        &graphics::Mesh::new_rectangle(
+                            ctx,
+                            graphics::DrawMode::fill(),
+                            Rect::new(0., 0., square, square),
+                            color,
+                        )?,
+                        pos,
+                    );
                 }
 
-                canvas.draw(
-                    graphics::Text::new(piece_text)
-                        .set_scale(win_w/8.),
-                    DrawParam::default()
-                        .dest(pos)
-                        .color(if piece_white {
-                            graphics::Color::from([1., 1., 1., 1.])
-                        } else {
-                            graphics::Color::from([0., 0., 0., 1.])
-                        }),
-                );
+                /* hovering only previews moves while nothing is selected */
+                let (targets, alpha) = if self.selected.is_some() {
+                    (&self.moves, 0.6)
+                } else {
+                    (&self.hover_moves, 0.3)
+                };
+                if let Some(mv) = targets.get(&(j, i)) {
+                    let size = square;
+                    let center = Vec2::splat(square/2.);
+                    let color = graphics::Color::from([0., 0., 0., alpha]);
+
+                    /* a ring around pieces that can be captured, a dot on
+                     * empty squares */
+                    let marker = if mv.capture {
+                        graphics::Mesh::new_circle(
+                            ctx,
+                            graphics::DrawMode::stroke(size*0.08),
+                            center,
+                            size*0.42,
+                            0.5,
+                            color,
+                        )?
+                    } else {
+                        graphics::Mesh::new_circle(
+                            ctx,
+                            graphics::DrawMode::fill(),
+                            center,
+                            size*0.15,
+                            0.5,
+                            color,
+                        )?
+                    };
+                    canvas.draw(&marker, pos);
+                }
+
+                let arriving = match &self.animation {
+                    Some(anim) => anim.is_arriving(&(j, i)),
+                    None => false,
+                };
+                if !arriving || self.view.is_some() {
+                    draw_piece(&mut canvas, &self.pieces, piece_text,
+                               *piece_white, pos, square, 1.);
+                }
             }
         }
 
+        if let (Some(anim), None) = (&self.animation, self.view) {
+            let t = anim.progress();
+
+            for fade in anim.fades.iter() {
+                let pos = self.square_pos(&fade.loc, &layout);
+                draw_piece(&mut canvas, &self.pieces, &fade.piece,
+                           fade.white, pos, square, 1. - t);
+            }
+
+            for slide in anim.slides.iter() {
+                let from = self.square_pos(&slide.from, &layout);
+                let to = self.square_pos(&slide.to, &layout);
+                draw_piece(&mut canvas, &self.pieces, &slide.piece,
+                           slide.white, from.lerp(to, t), square, 1.);
+            }
+        }
+
+        let size = square;
+        let center = Vec2::splat(square/2.);
+        let annotations = &self.history.get(self.shown_ply()).annotations;
+
+        for (loc, color) in annotations.squares.iter() {
+            canvas.draw(
+                &graphics::Mesh::new_circle(
+                    ctx,
+                    graphics::DrawMode::stroke(size*0.06),
+                    center,
+                    size*0.45,
+                    0.5,
+                    graphics::Color::from(color.rgba()),
+                )?,
+                self.square_pos(loc, &layout),
+            );
+        }
+
+        for arrow in annotations.arrows.iter() {
+            draw_arrow(
+                ctx,
+                &mut canvas,
+                self.square_pos(&arrow.from, &layout) + center,
+                self.square_pos(&arrow.to, &layout) + center,
+                size*0.15,
+                graphics::Color::from(arrow.color.rgba()),
+            )?;
+        }
+
+        self.draw_panel(&mut canvas, &layout);
+
         let joever_text: Option<String>
             = match self.game.as_mut().unwrap().get_state() {
             ChessState::Ongoing => None,
@@ -145,7 +868,7 @@ impl<'a> MainState<'a> {
 
             canvas.draw(
                 graphics::Text::new(text)
-                    .set_scale(100.),
+                    .set_scale(layout.text(BANNER_TEXT_SIZE)),
                 DrawParam::default()
                     .dest(Vec2::new(0., 0.))
                     .color(graphics::Color::from([1., 1., 1., 1.])),
@@ -154,34 +877,104 @@ impl<'a> MainState<'a> {
 
         canvas.finish(ctx)?;
 
-        self.can_wait = self.turn % 2 == 1;
+        self.can_wait = self.turn % 2 == 1
+            || self.game.as_ref().unwrap().is_spectating();
 
         return Ok(());
     }
 
+    /**
+     * Draw the moves played so far into the side panel. Only the most recent
+     * moves are shown if they do not all fit.
+     */
+    fn draw_panel(&self, canvas: &mut graphics::Canvas, layout: &Layout) {
+        let line_h = layout.text(PANEL_TEXT_SIZE) * 1.25;
+        let max_lines = ((layout.panel.h / line_h) as usize).max(3) - 2;
+
+        let mut lines: Vec<String> = Vec::new();
+        for ply in 1..=self.history.last_ply() {
+            let notation = self.history.get(ply).notation.as_deref()
+                .unwrap_or("");
+            if ply % 2 == 1 {
+                lines.push(format!("{}. {}", ply / 2 + 1, notation));
+            } else if let Some(line) = lines.last_mut() {
+                *line += &format!(" {}", notation);
+            }
+        }
+        let skip = lines.len().saturating_sub(max_lines);
+
+        let mut text = if self.settings.player_name.is_empty() {
+            String::new()
+        } else {
+            format!("{}\n\n", self.settings.player_name)
+        };
+        if self.show_info {
+            text += "Game info (I for moves)\n";
+            text += &self.game.as_ref().unwrap().rules().join("\n");
+        } else {
+            text += "Moves (I for game info)\n";
+            text += &lines[skip..].join("\n");
+        }
+        if let Some(ply) = self.view {
+            text += &format!("\n\nReviewing {}/{}\n(left/right, end)",
+                             ply, self.history.last_ply());
+        }
+        if self.game.as_ref().unwrap().is_spectating() {
+            text += "\n\nSpectating";
+        }
+        if !self.game.as_ref().unwrap().is_connected() {
+            text += "\n\nConnection lost\n(R to reconnect, Esc for menu)";
+        }
+
+        canvas.draw(
+            graphics::Text::new(text)
+                .set_scale(layout.text(PANEL_TEXT_SIZE))
+                .set_bounds(Vec2::new(layout.panel.w, layout.panel.h)),
+            DrawParam::default()
+                .dest(layout.panel.point())
+                .color(graphics::Color::from([1., 1., 1., 1.])),
+        );
+    }
+
     fn ingame_mouse_button_down_event(
         &mut self,
         ctx: &mut Context,
-        _button: event::MouseButton,
+        button: event::MouseButton,
         x: f32,
         y: f32,
     ) -> GameResult {
-        let (win_w, win_h) = ctx.gfx.drawable_size();
+        let layout = Layout::new(ctx);
+
+        let pos = match self.square_at(x, y, &layout) {
+            Some((file, rank)) => IVec2::new(file, rank),
+            None => return Ok(()),
+        };
+
+        if button == event::MouseButton::Right {
+            let color = AnnotationColor::from_mods(ctx.keyboard.active_mods());
+            self.annotation_start = Some(((pos.x, pos.y), color));
+            return Ok(());
+        }
+        if button != event::MouseButton::Left {
+            return Ok(());
+        }
+
+        let ply = self.shown_ply();
+        self.history.get_mut(ply).annotations.clear();
+
+        /* past positions can only be looked at */
+        if self.view.is_some() {
+            return Ok(());
+        }
 
-        let pos = IVec2::new(
-            (x*8. / win_w).floor() as i32,
-            if !self.game.as_mut().unwrap().get_player() && self.flip_mode {
-                (y*8. / win_h).floor() as i32
-            } else {
-                7 - (y*8. / win_h).floor() as i32
-            },
-        );
         match self.moves.get(&(pos.x, pos.y)) {
             Some(mv) => {
                 if self.turn % 2 == 0 {
                     if self.game.as_mut().unwrap().apply_move(&mv) {
                         self.turn += 1;
+                        self.board_changed(ctx);
                     }
+                    self.check_game();
                 }
 
                 self.selected = None;
@@ -203,20 +996,137 @@ impl<'a> MainState<'a> {
         return Ok(());
     }
 
+    fn ingame_mouse_button_up_event(
+        &mut self,
+        ctx: &mut Context,
+        button: event::MouseButton,
+        x: f32,
+        y: f32,
+    ) -> GameResult {
+        if button != event::MouseButton::Right {
+            return Ok(());
+        }
+
+        let layout = Layout::new(ctx);
+        let (start, color) = match self.annotation_start.take() {
+            Some(start) => start,
+            None => return Ok(()),
+        };
+        let end = match self.square_at(x, y, &layout) {
+            Some(loc) => loc,
+            None => return Ok(()),
+        };
+
+        let ply = self.shown_ply();
+        let annotations = &mut self.history.get_mut(ply).annotations;
+        if start == end {
+            annotations.toggle_square(end, color);
+        } else {
+            annotations.toggle_arrow(start, end, color);
+        }
+
+        return Ok(());
+    }
+
+    fn ingame_mouse_motion_event(
+        &mut self,
+        ctx: &mut Context,
+        x: f32,
+        y: f32,
+    ) -> GameResult {
+        let layout = Layout::new(ctx);
+
+        let loc = self.square_at(x, y, &layout);
+        if loc == self.hovered {
+            return Ok(());
+        }
+        self.hovered = loc;
+
+        self.hover_moves = match loc {
+            Some(loc) if self.turn % 2 == 0 && self.view.is_none()
+                => self.game.as_mut().unwrap().get_moves(&loc),
+            _ => HashMap::new(),
+        };
+
+        return Ok(());
+    }
+
     fn ingame_key_down_event(
         &mut self,
         ctx: &mut Context,
-        input: ggez::input::keyboard::KeyInput,
+        input: KeyInput,
         _repeated: bool,
     ) -> GameResult {
         if let Some(key) = input.keycode {
             match key {
-                VirtualKeyCode::F => self.flip_mode = !self.flip_mode,
+                VirtualKeyCode::F => {
+                    self.settings.flip_mode = !self.settings.flip_mode;
+                    self.save_settings();
+                },
                 VirtualKeyCode::Q => ctx.request_quit(),
-                VirtualKeyCode::M => if self.music.paused() {
-                    self.music.resume();
-                } else {
-                    self.music.pause();
+                VirtualKeyCode::R => self.reconnect(ctx),
+                VirtualKeyCode::I => self.show_info = !self.show_info,
+                VirtualKeyCode::Escape
+                    if !self.game.as_ref().unwrap().is_connected() => {
+                    self.game = None;
+                    self.can_wait = false;
+                    self.selected = None;
+                    self.moves = HashMap::new();
+                    self.open_form(GameState::Init, Vec::new());
+                },
+                VirtualKeyCode::Minus => self.change_anim_duration(-0.05),
+                VirtualKeyCode::Equals => self.change_anim_duration(0.05),
+                VirtualKeyCode::T => {
+                    let next = (theme_index(&self.settings.theme) + 1)
+                        % THEMES.len();
+                    self.settings.theme = String::from(THEMES[next].name);
+                    self.save_settings();
+                },
+                VirtualKeyCode::P => {
+                    let sets = PieceSet::available(ctx);
+                    let next = match sets.iter()
+                        .position(|s| *s == self.pieces.name) {
+                        Some(i) => (i + 1) % sets.len(),
+                        None => 0,
+                    };
+                    self.pieces = PieceSet::load(ctx, &sets[next]);
+                    self.settings.piece_set = self.pieces.name.clone();
+                    self.save_settings();
+                },
+                VirtualKeyCode::M => {
+                    self.settings.music_muted = !self.settings.music_muted;
+                    self.music.set_muted(self.settings.music_muted);
+                    self.save_settings();
+                },
+                VirtualKeyCode::N => {
+                    self.settings.effects_muted = !self.settings.effects_muted;
+                    self.sounds.muted = self.settings.effects_muted;
+                    self.save_settings();
+                },
+                VirtualKeyCode::LBracket => self.change_music_volume(-0.1),
+                VirtualKeyCode::RBracket => self.change_music_volume(0.1),
+                VirtualKeyCode::Comma => self.change_effects_volume(-0.1),
+                VirtualKeyCode::Period => self.change_effects_volume(0.1),
+
+                VirtualKeyCode::Left => {
+                    self.view = Some(self.shown_ply().saturating_sub(1));
+                    self.selected = None;
+                    self.moves = HashMap::new();
+                },
+                VirtualKeyCode::Right => {
+                    let ply = self.shown_ply() + 1;
+                    self.view = if ply >= self.history.last_ply() {
+                        None
+                    } else {
+                        Some(ply)
+                    };
+                },
+                VirtualKeyCode::End => self.view = None,
+                VirtualKeyCode::E => match self.export_pgn(ctx) {
+                    Ok(path) => self.notifications
+                        .info(format!("Saved game to {}", path.display())),
+                    Err(e) => self.notifications
+                        .error(format!("Could not save game: {}", e)),
                 },
                 _ => (),
             }
@@ -238,8 +1148,10 @@ a number key:
 1) Local game
 2) Join remote
 3) Host remote
+4) Settings
+5) Spectate
 q) Quit")
-                .set_scale(50.),
+                .set_scale(MENU_TEXT_SIZE * ui_scale(ctx)),
             DrawParam::default()
                 .dest(Vec2::new(0., 0.))
                 .color(graphics::Color::from([1., 1., 1., 1.])),
@@ -253,7 +1165,7 @@ q) Quit")
     fn init_key_down_event(
         &mut self,
         ctx: &mut Context,
-        input: ggez::input::keyboard::KeyInput,
+        input: KeyInput,
         _repeated: bool,
     ) -> GameResult {
         if let Some(key) = input.keycode {
@@ -261,36 +1173,106 @@ q) Quit")
                 VirtualKeyCode::Q => ctx.request_quit(),
 
                 /* Play local game */
-                VirtualKeyCode::Key1 => {
-                    self.game = Some(Box::new(LocalGame::new()));
-                    self.state = GameState::InGame;
-                },
+                VirtualKeyCode::Key1
+                    => self.start_game(Box::new(LocalGame::new())),
 
                 /* Join remote game */
-                VirtualKeyCode::Key2
-                    => self.state = GameState::Joining,
+                VirtualKeyCode::Key2 => {
+                    let port = self.default_port_text();
+                    self.open_form(
+                        GameState::Joining,
+                        vec![TextInput::default(), TextInput::new(&port)],
+                    );
+                },
 
                 /* Host remote game */
-                VirtualKeyCode::Key3
-                    => self.state = GameState::Hosting,
+                VirtualKeyCode::Key3 => {
+                    let port = self.default_port_text();
+                    self.open_form(
+                        GameState::Hosting,
+                        vec![TextInput::new(&port)],
+                    );
+                },
+
+                VirtualKeyCode::Key4 => {
+                    let path = self.settings.music_path.clone()
+                        .unwrap_or_default();
+                    let port = self.default_port_text();
+                    self.open_form(
+                        GameState::Settings,
+                        vec![
+                            TextInput::new(&path),
+                            TextInput::new(&self.settings.player_name),
+                            TextInput::new(&port),
+                        ],
+                    );
+                },
+
+                /* Watch a hosted game */
+                VirtualKeyCode::Key5 => {
+                    let port = match self.settings.default_port {
+                        Some(port) => port.saturating_add(1).to_string(),
+                        None => String::new(),
+                    };
+                    self.open_form(
+                        GameState::Spectating,
+                        vec![TextInput::default(), TextInput::new(&port)],
+                    );
+                },
                 _ => (),
             }
         }
+        /* the menu key would otherwise end up in the new form's first field */
+        self.skip_text = !matches!(self.state, GameState::Init);
 
         return Ok(());
     }
 
-/********************** GameState::Joining **********************/
-    fn joining_draw(&mut self, ctx: &mut Context) -> GameResult {
+/********************** Text input forms **********************/
+    /**
+     * Switch to a screen with the given text fields, focusing the first one.
+     */
+    fn open_form(&mut self, state: GameState, inputs: Vec<TextInput>) {
+        self.inputs = inputs;
+        self.focus = 0;
+        self.input_error = None;
+        self.lan_selected = None;
+        self.lan = None;
+        self.lan_error = None;
+        if let GameState::Joining = state {
+            match Browser::new() {
+                Ok(browser) => self.lan = Some(browser),
+                Err(e) => self.lan_error = Some(e.to_string()),
+            }
+        }
+        self.state = state;
+    }
+
+    fn default_port_text(&self) -> String {
+        return match self.settings.default_port {
+            Some(port) => port.to_string(),
+            None => String::new(),
+        };
+    }
+
+    fn form_field(&self, i: usize) -> String {
+        return self.inputs[i].display(i == self.focus);
+    }
+
+    fn form_draw(&mut self, ctx: &mut Context, text: String) -> GameResult {
         let mut canvas = graphics::Canvas::from_frame(
             ctx,
             graphics::Color::from([0.1, 0.2, 0.3, 1.0])
         );
 
+        let text = match &self.input_error {
+            Some(e) => format!("{}\n\n{}", text, e),
+            None => text,
+        };
+
         canvas.draw(
-            graphics::Text::new(format!("Type IP:port (q to return)\n{}",
-                                        self.buf))
-                .set_scale(50.),
+            graphics::Text::new(text)
+                .set_scale(MENU_TEXT_SIZE * ui_scale(ctx)),
             DrawParam::default()
                 .dest(Vec2::new(0., 0.))
                 .color(graphics::Color::from([1., 1., 1., 1.])),
@@ -301,120 +1283,389 @@ q) Quit")
         return Ok(());
     }
 
+    /**
+     * Keys shared by all forms: Tab moves to the next field, Esc returns to
+     * the menu and everything else goes to the focused field.
+     */
+    fn form_key_down_event(&mut self, input: &KeyInput) {
+        match input.keycode {
+            Some(VirtualKeyCode::Tab) => if !self.inputs.is_empty() {
+                self.focus = (self.focus + 1) % self.inputs.len();
+            },
+            Some(VirtualKeyCode::Escape)
+                => self.open_form(GameState::Init, Vec::new()),
+            _ => if let Some(field) = self.inputs.get_mut(self.focus) {
+                field.key_down(input);
+            },
+        }
+    }
+
+    fn form_text_input_event(
+        &mut self,
+        _ctx: &mut Context,
+        character: char,
+    ) -> GameResult {
+        if self.skip_text {
+            self.skip_text = false;
+            return Ok(());
+        }
+        if let Some(field) = self.inputs.get_mut(self.focus) {
+            field.insert(character);
+        }
+
+        return Ok(());
+    }
+
+/********************** GameState::Joining **********************/
+    fn joining_draw(&mut self, ctx: &mut Context) -> GameResult {
+        let mut text = format!("Join a game (Tab to switch, Esc to return)
+Address:
+{}
+Port:
+{}", self.form_field(0), self.form_field(1));
+
+        if !self.settings.recent_addresses.is_empty() {
+            text += "\n\nRecent:";
+            for (i, addr) in self.settings.recent_addresses.iter().enumerate() {
+                text += &format!("\nF{}) {}", i + 1, addr);
+            }
+        }
+
+        if let Some(lan) = &self.lan {
+            text += "\n\nLAN games (Up/Down to pick, Enter to join):";
+            if lan.games().is_empty() {
+                text += "\n(searching)";
+            }
+            for (i, game) in lan.games().iter().enumerate() {
+                let marker = if self.lan_selected == Some(i) {
+                    ">"
+                } else {
+                    " "
+                };
+                text += &format!("\n{} {} ({})", marker,
+                                 game.advert.describe(), game.addr);
+            }
+        }
+        if let Some(e) = &self.lan_error {
+            text += &format!("\n\nLAN games cannot be listed: {}", e);
+        }
+
+        return self.form_draw(ctx, text);
+    }
+
     fn joining_key_down_event(
         &mut self,
         _ctx: &mut Context,
-        input: ggez::input::keyboard::KeyInput,
+        input: KeyInput,
         _repeated: bool,
     ) -> GameResult {
-        if let Some(key) = input.keycode {
-            match key {
-                VirtualKeyCode::Key1 => self.buf.push('1'),
-                VirtualKeyCode::Key2 => self.buf.push('2'),
-                VirtualKeyCode::Key3 => self.buf.push('3'),
-                VirtualKeyCode::Key4 => self.buf.push('4'),
-                VirtualKeyCode::Key5 => self.buf.push('5'),
-                VirtualKeyCode::Key6 => self.buf.push('6'),
-                VirtualKeyCode::Key7 => self.buf.push('7'),
-                VirtualKeyCode::Key8 => self.buf.push('8'),
-                VirtualKeyCode::Key9 => self.buf.push('9'),
-                VirtualKeyCode::Key0 => self.buf.push('0'),
-
-                VirtualKeyCode::Period => self.buf.push('.'),
-                VirtualKeyCode::Colon => self.buf.push(':'),
-
-                VirtualKeyCode::Back => _ = self.buf.pop(),
-                VirtualKeyCode::Q => {
-                    self.state = GameState::Init;
-                    self.buf = String::new();
-                },
+        let recent = match input.keycode {
+            Some(VirtualKeyCode::F1) => Some(0),
+            Some(VirtualKeyCode::F2) => Some(1),
+            Some(VirtualKeyCode::F3) => Some(2),
+            Some(VirtualKeyCode::F4) => Some(3),
+            Some(VirtualKeyCode::F5) => Some(4),
+            _ => None,
+        };
+        if let Some(addr) = recent
+            .and_then(|i| self.settings.recent_addresses.get(i)) {
+            /* the recent addresses already include the port */
+            self.inputs = vec![TextInput::new(addr), TextInput::default()];
+            self.focus = 0;
+            return Ok(());
+        }
 
-                VirtualKeyCode::Return => {
-                    self.game =
-                        Some(Box::new(RemoteGame::new(&self.buf)?));
-                    self.state = GameState::InGame;
-                },
+        let lan_games = self.lan.as_ref().map_or(0, |lan| lan.games().len());
+        match input.keycode {
+            Some(VirtualKeyCode::Down) if lan_games > 0 => {
+                self.lan_selected = Some(match self.lan_selected {
+                    Some(i) => (i + 1).min(lan_games - 1),
+                    None => 0,
+                });
+                return Ok(());
+            },
+            /* moving up past the first game goes back to the fields */
+            Some(VirtualKeyCode::Up) => {
+                self.lan_selected = self.lan_selected
+                    .and_then(|i| i.checked_sub(1));
+                return Ok(());
+            },
+            Some(VirtualKeyCode::Return) => (),
+            _ => {
+                self.lan_selected = None;
+                self.form_key_down_event(&input);
+                return Ok(());
+            },
+        }
 
-                _ => (),
+        let lan_addr = self.lan_selected.and_then(|i| {
+            self.lan.as_ref()?.games().get(i).map(|game| game.addr)
+        });
+        if let Some(addr) = lan_addr {
+            if self.join_game(TcpConnector::new(addr), Color::White) {
+                self.remember_address(&addr.to_string(), "", addr.port());
             }
+            return Ok(());
+        }
+
+        let host = self.inputs[0].text();
+        let port = self.inputs[1].text();
+        match resolve_address(&host, &port) {
+            Ok(addr) => if self.join_game(TcpConnector::new(addr),
+                                          Color::White) {
+                self.remember_address(&host, &port, addr.port());
+            },
+            Err(e) => self.input_error = Some(e),
         }
 
         return Ok(());
     }
 
+    /**
+     * Add the address as typed by the user to the recent addresses, so host
+     * names get resolved again the next time.
+     */
+    fn remember_address(&mut self, host: &str, port: &str, resolved_port: u16) {
+        let (host, port) = (host.trim(), port.trim());
+        let addr = if port.is_empty() {
+            String::from(host)
+        } else if host.contains(':') && !host.starts_with('[') {
+            format!("[{}]:{}", host, port)
+        } else {
+            format!("{}:{}", host, port)
+        };
+
+        self.settings.add_recent_address(addr);
+        self.settings.default_port = Some(resolved_port);
+        self.save_settings();
+    }
+
 /********************** GameState::Hosting **********************/
     fn hosting_draw(&mut self, ctx: &mut Context) -> GameResult {
-        let mut canvas = graphics::Canvas::from_frame(
-            ctx,
-            graphics::Color::from([0.1, 0.2, 0.3, 1.0])
-        );
+        let on = |allowed: bool| if allowed { "on" } else { "off" };
+        let text = format!("Host a game (Esc to return)
+Port:
+{}
 
-        canvas.draw(
-            graphics::Text::new(format!("Type port (q to return)\n{}",
-                                        self.buf))
-                .set_scale(50.),
-            DrawParam::default()
-                .dest(Vec2::new(0., 0.))
-                .color(graphics::Color::from([1., 1., 1., 1.])),
-        );
+F1) Castling: {}
+F2) En passant: {}", self.form_field(0), on(self.host_rules.castling),
+                      on(self.host_rules.en_passant));
 
-        canvas.finish(ctx)?;
+        return self.form_draw(ctx, text);
+    }
+
+    fn hosting_key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        input: KeyInput,
+        _repeated: bool,
+    ) -> GameResult {
+        match input.keycode {
+            Some(VirtualKeyCode::F1) => {
+                self.host_rules.castling = !self.host_rules.castling;
+                return Ok(());
+            },
+            Some(VirtualKeyCode::F2) => {
+                self.host_rules.en_passant = !self.host_rules.en_passant;
+                return Ok(());
+            },
+            Some(VirtualKeyCode::Return) => (),
+            _ => {
+                self.form_key_down_event(&input);
+                return Ok(());
+            },
+        }
+
+        match parse_port(&self.inputs[0].text()) {
+            Ok(port) => {
+                let spectators = self.spectator_acceptor(port);
+                let advert = Some(self.lan_advert(port));
+                if self.host_game(TcpAcceptor::bind(port), spectators,
+                                  advert, self.host_rules) {
+                    self.settings.default_port = Some(port);
+                    self.save_settings();
+                }
+            },
+            Err(e) => self.input_error = Some(e),
+        }
 
         return Ok(());
     }
 
-    fn hosting_key_down_event(
+/********************** GameState::WaitingForClient **********************/
+    fn waiting_draw(&mut self, ctx: &mut Context) -> GameResult {
+        let address = self.waiting.as_ref()
+            .map_or("", |waiting| waiting.address.as_str());
+        let text = format!("Waiting for a client (Esc to cancel)
+Address:
+{}", address);
+
+        return self.form_draw(ctx, text);
+    }
+
+    fn waiting_key_down_event(
         &mut self,
         _ctx: &mut Context,
-        input: ggez::input::keyboard::KeyInput,
+        input: KeyInput,
         _repeated: bool,
     ) -> GameResult {
-        if let Some(key) = input.keycode {
-            match key {
-                VirtualKeyCode::Key1 => self.buf.push('1'),
-                VirtualKeyCode::Key2 => self.buf.push('2'),
-                VirtualKeyCode::Key3 => self.buf.push('3'),
-                VirtualKeyCode::Key4 => self.buf.push('4'),
-                VirtualKeyCode::Key5 => self.buf.push('5'),
-                VirtualKeyCode::Key6 => self.buf.push('6'),
-                VirtualKeyCode::Key7 => self.buf.push('7'),
-                VirtualKeyCode::Key8 => self.buf.push('8'),
-                VirtualKeyCode::Key9 => self.buf.push('9'),
-                VirtualKeyCode::Key0 => self.buf.push('0'),
-
-                VirtualKeyCode::Back => _ = self.buf.pop(),
-                VirtualKeyCode::Q => {
-                    self.state = GameState::Init;
-                    self.buf = String::new();
-                },
+        if input.keycode == Some(VirtualKeyCode::Escape) {
+            self.cancel_hosting();
+        }
 
-                VirtualKeyCode::Return => {
-                    self.game =
-                        Some(Box::new(RemoteHostGame::new(&self.buf)?));
-                    self.state = GameState::InGame;
-                },
+        return Ok(());
+    }
 
-                _ => (),
+/********************** GameState::Spectating **********************/
+    fn spectating_draw(&mut self, ctx: &mut Context) -> GameResult {
+        let text = format!("Watch a game (Tab to switch, Esc to return)
+Address:
+{}
+Spectator port, one after
+the game's port:
+{}", self.form_field(0), self.form_field(1));
+
+        return self.form_draw(ctx, text);
+    }
+
+    fn spectating_key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        input: KeyInput,
+        _repeated: bool,
+    ) -> GameResult {
+        if input.keycode != Some(VirtualKeyCode::Return) {
+            self.form_key_down_event(&input);
+            return Ok(());
+        }
+
+        match resolve_address(&self.inputs[0].text(), &self.inputs[1].text()) {
+            Ok(addr) => {
+                self.spectate_game(TcpConnector::new(addr));
+            },
+            Err(e) => self.input_error = Some(e),
+        }
+
+        return Ok(());
+    }
+
+/********************** GameState::Settings **********************/
+    fn settings_draw(&mut self, ctx: &mut Context) -> GameResult {
+        let current = match &self.settings.music_path {
+            Some(path) => path.as_str(),
+            None => "(default)",
+        };
+
+        let text = format!("Settings (Tab to switch, Esc to return)
+Music file or directory,
+empty for the default:
+{}
+Current: {}
+
+Player name:
+{}
+
+Default port:
+{}
+
+F1) Verify servers: {}", self.form_field(0), current, self.form_field(1),
+                         self.form_field(2),
+                         verification_name(self.settings.verification));
+
+        return self.form_draw(ctx, text);
+    }
+
+    fn settings_key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        input: KeyInput,
+        _repeated: bool,
+    ) -> GameResult {
+        /* takes effect in the next game, so it is saved right away */
+        if input.keycode == Some(VirtualKeyCode::F1) {
+            self.settings.verification = match self.settings.verification {
+                Verification::Off => Verification::Warn,
+                Verification::Warn => Verification::Abort,
+                Verification::Abort => Verification::Off,
+            };
+            self.save_settings();
+            return Ok(());
+        }
+
+        if input.keycode != Some(VirtualKeyCode::Return) {
+            self.form_key_down_event(&input);
+            return Ok(());
+        }
+
+        let port = self.inputs[2].text();
+        self.settings.default_port = if port.trim().is_empty() {
+            None
+        } else {
+            match parse_port(&port) {
+                Ok(port) => Some(port),
+                Err(e) => {
+                    self.input_error = Some(e);
+                    return Ok(());
+                },
             }
+        };
+        self.settings.player_name = String::from(self.inputs[1].text().trim());
+
+        let path = self.inputs[0].text();
+        let path = path.trim();
+        let music_path = if path.is_empty() {
+            None
+        } else {
+            Some(String::from(path))
+        };
+        if music_path != self.settings.music_path {
+            self.settings.music_path = music_path;
+            self.music = Music::new(
+                ctx,
+                self.settings.music_path.as_deref(),
+                self.settings.music_volume,
+                self.settings.music_muted,
+            );
         }
 
+        self.save_settings();
+        self.input_error = Some(String::from("Saved"));
+
         return Ok(());
     }
 }
 
 impl event::EventHandler<GameError> for MainState<'_> {
-    fn update(&mut self, _ctx: &mut Context) -> GameResult {
-        if !self.music.playing() {
-            let _ = self.music.play_later();
-            self.music.pause();
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        self.music.update(ctx);
+        self.notifications.update(ctx.time.delta().as_secs_f32());
+
+        if let Some(lan) = self.lan.as_mut() {
+            lan.poll();
+            /* games come and go, keep the choice in range */
+            if self.lan_selected.is_some_and(|i| i >= lan.games().len()) {
+                self.lan_selected = None;
+            }
+        }
+
+        self.poll_client();
+
+        if let Some(anim) = self.animation.as_mut() {
+            let dt = ctx.time.delta().as_secs_f32().min(MAX_ANIM_STEP);
+            if anim.update(dt) {
+                self.animation = None;
+            }
         }
 
         /* this is so incredibly bad and probably buggy */
-        if self.can_wait {
+        /* let our own move finish animating before blocking on the opponent */
+        if self.can_wait && self.animation.is_none() {
             if self.game.as_mut().unwrap().wait_move() {
                 self.turn += 1;
+                self.board_changed(ctx);
             }
             self.can_wait = false;
+            self.check_game();
         }
 
         return Ok(());
@@ -423,12 +1674,17 @@ impl event::EventHandler<GameError> for MainState<'_> {
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
         use GameState::*;
 
-        return match self.state {
+        match self.state {
             Init => self.init_draw(ctx),
             Joining => self.joining_draw(ctx),
             Hosting => self.hosting_draw(ctx),
+            WaitingForClient => self.waiting_draw(ctx),
             InGame => self.ingame_draw(ctx),
-        };
+            Settings => self.settings_draw(ctx),
+            Spectating => self.spectating_draw(ctx),
+        }?;
+
+        return self.notifications.draw(ctx);
     }
 
     fn mouse_button_down_event(
@@ -446,24 +1702,88 @@ impl event::EventHandler<GameError> for MainState<'_> {
         };
     }
 
+    fn mouse_button_up_event(
+        &mut self,
+        ctx: &mut Context,
+        button: event::MouseButton,
+        x: f32,
+        y: f32,
+    ) -> GameResult {
+        use GameState::*;
+
+        return match self.state {
+            InGame => self.ingame_mouse_button_up_event(ctx, button, x, y),
+            _ => Ok(()),
+        };
+    }
+
+    fn mouse_motion_event(
+        &mut self,
+        ctx: &mut Context,
+        x: f32,
+        y: f32,
+        _dx: f32,
+        _dy: f32,
+    ) -> GameResult {
+        use GameState::*;
+
+        return match self.state {
+            InGame => self.ingame_mouse_motion_event(ctx, x, y),
+            _ => Ok(()),
+        };
+    }
+
     fn key_down_event(
         &mut self,
         ctx: &mut Context,
-        input: ggez::input::keyboard::KeyInput,
+        input: KeyInput,
         repeated: bool,
     ) -> GameResult {
         use GameState::*;
 
+        /* only the text of the key that opened a form is skipped */
+        self.skip_text = false;
         return match self.state {
             Init => self.init_key_down_event(ctx, input, repeated),
             Joining => self.joining_key_down_event(ctx, input, repeated),
             Hosting => self.hosting_key_down_event(ctx, input, repeated),
+            WaitingForClient
+                => self.waiting_key_down_event(ctx, input, repeated),
             InGame => self.ingame_key_down_event(ctx, input, repeated),
+            Settings => self.settings_key_down_event(ctx, input, repeated),
+            Spectating
+                => self.spectating_key_down_event(ctx, input, repeated),
+        };
+    }
+
+    fn text_input_event(
+        &mut self,
+        ctx: &mut Context,
+        character: char,
+    ) -> GameResult {
+        use GameState::*;
+
+        return match self.state {
+            Joining | Hosting | Settings | Spectating
+                => self.form_text_input_event(ctx, character),
+            _ => Ok(()),
         };
     }
 }
 
 fn main() -> GameResult {
+    let args = match cli::parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        },
+    };
+    if let Launch::Help = args.launch {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+
     let resource_dir = if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
         let mut path = path::PathBuf::from(manifest_dir);
         path.push("resources");
@@ -474,9 +1794,13 @@ fn main() -> GameResult {
 
     let cb = ggez::ContextBuilder::new("Chess", "EmmaEricsson")
         .add_resource_path(resource_dir)
-        .window_mode(WindowMode::default().dimensions(800., 800.))
+        .window_mode(WindowMode::default()
+            .dimensions(1000., 800.)
+            .min_dimensions(400., 300.)
+            .resizable(true))
         .window_setup(WindowSetup::default().title("Chessss"));
     let (mut ctx, event_loop) = cb.build()?;
-    let state = MainState::new(&mut ctx)?;
+    let mut state = MainState::new(&mut ctx)?;
+    state.launch(&args)?;
     event::run(ctx, event_loop, state);
 }
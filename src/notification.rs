@@ -0,0 +1,103 @@
+use ggez::{Context, GameResult};
+use ggez::glam::Vec2;
+use ggez::graphics::{self, DrawParam, Rect};
+
+use crate::layout::ui_scale;
+
+/// Seconds a notification stays on screen
+const INFO_DURATION: f32 = 3.;
+const ERROR_DURATION: f32 = 6.;
+
+/// Text size in logical pixels
+const TEXT_SIZE: f32 = 24.;
+
+struct Notification {
+    text: String,
+    error: bool,
+    remaining: f32,
+}
+
+/**
+ * Short messages drawn on top of whatever screen is shown, e.g. connection
+ * errors or illegal moves reported by the server.
+ */
+#[derive(Default)]
+pub struct Notifications {
+    list: Vec<Notification>,
+}
+
+impl Notifications {
+    pub fn info(&mut self, text: impl Into<String>) {
+        self.push(text.into(), false, INFO_DURATION);
+    }
+
+    pub fn error(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        eprintln!("{}", text);
+        self.push(text, true, ERROR_DURATION);
+    }
+
+    fn push(&mut self, text: String, error: bool, remaining: f32) {
+        self.list.push(Notification { text, error, remaining });
+    }
+
+    /**
+     * Remove notifications that have been shown for long enough.
+     */
+    pub fn update(&mut self, dt: f32) {
+        for n in self.list.iter_mut() {
+            n.remaining -= dt;
+        }
+        self.list.retain(|n| n.remaining > 0.);
+    }
+
+    /**
+     * Draw the notifications stacked at the bottom of the window. This uses a
+     * canvas of its own, so it has to be called after the screen is drawn.
+     */
+    pub fn draw(&self, ctx: &mut Context) -> GameResult {
+        if self.list.is_empty() {
+            return Ok(());
+        }
+
+        let (win_w, win_h) = ctx.gfx.drawable_size();
+        let scale = ui_scale(ctx);
+        let pad = 8. * scale;
+        let mut canvas = graphics::Canvas::from_frame(ctx, None);
+
+        let mut y = win_h;
+        for n in self.list.iter().rev() {
+            let mut text = graphics::Text::new(n.text.as_str());
+            text.set_scale(TEXT_SIZE * scale)
+                .set_bounds(Vec2::new(win_w - 4.*pad, f32::INFINITY));
+            let size = text.measure(ctx)?;
+
+            y -= size.y + 3.*pad;
+            let background = if n.error {
+                [0.6, 0.1, 0.1, 0.9]
+            } else {
+                [0.1, 0.1, 0.1, 0.9]
+            };
+            let rect = Rect::new(pad, y, win_w - 2.*pad, size.y + 2.*pad);
+            canvas.draw(
+                &graphics::Mesh::new_rectangle(
+                    ctx,
+                    graphics::DrawMode::fill(),
+                    rect,
+                    graphics::Color::from(background),
+                )?,
+                DrawParam::default(),
+            );
+            canvas.draw(
+                &text,
+                DrawParam::default()
+                    .dest(Vec2::new(2.*pad, y + pad))
+                    .color(graphics::Color::from([1., 1., 1., 1.])),
+            );
+        }
+
+        canvas.finish(ctx)?;
+
+        return Ok(());
+    }
+}
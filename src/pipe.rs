@@ -0,0 +1,161 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write, ErrorKind};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+
+use crate::connection::POLL_TIMEOUT;
+use crate::transport::{Acceptor, Connector, Nonblocking};
+
+/**
+ * One direction of a [PipeStream].
+ */
+#[derive(Default)]
+struct PipeBuffer {
+    data: VecDeque<u8>,
+    closed: bool,
+}
+
+type SharedBuffer = Arc<(Mutex<PipeBuffer>, Condvar)>;
+
+/**
+ * One end of an in-memory connection, see [pipe]. Dropping it closes the
+ * connection for the other end.
+ */
+pub struct PipeStream {
+    incoming: SharedBuffer,
+    outgoing: SharedBuffer,
+}
+
+/**
+ * Create two connected in-memory streams.
+ */
+pub fn pipe() -> (PipeStream, PipeStream) {
+    let a: SharedBuffer = Arc::default();
+    let b: SharedBuffer = Arc::default();
+    return (
+        PipeStream { incoming: a.clone(), outgoing: b.clone() },
+        PipeStream { incoming: b, outgoing: a },
+    );
+}
+
+impl Read for PipeStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (lock, cvar) = &*self.incoming;
+        let mut pipe = lock.lock().unwrap();
+        if pipe.data.is_empty() && !pipe.closed {
+            pipe = cvar.wait_timeout(pipe, POLL_TIMEOUT).unwrap().0;
+        }
+
+        if pipe.data.is_empty() {
+            if pipe.closed {
+                return Ok(0);
+            }
+            return Err(io::Error::from(ErrorKind::WouldBlock));
+        }
+
+        let n = buf.len().min(pipe.data.len());
+        for (dst, src) in buf.iter_mut().zip(pipe.data.drain(..n)) {
+            *dst = src;
+        }
+        return Ok(n);
+    }
+}
+
+impl Write for PipeStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let (lock, cvar) = &*self.outgoing;
+        let mut pipe = lock.lock().unwrap();
+        if pipe.closed {
+            return Err(io::Error::from(ErrorKind::BrokenPipe));
+        }
+        pipe.data.extend(buf);
+        cvar.notify_all();
+        return Ok(buf.len());
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+/* writes never wait and reads only for a moment anyway */
+impl Nonblocking for PipeStream {
+    fn set_nonblocking(&self, _nonblocking: bool) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+impl Drop for PipeStream {
+    fn drop(&mut self) {
+        for shared in [&self.incoming, &self.outgoing] {
+            let (lock, cvar) = &**shared;
+            lock.lock().unwrap().closed = true;
+            cvar.notify_all();
+        }
+    }
+}
+
+/**
+ * Hands out [PipeStream]s to a [PipeAcceptor], see [pipe_transport].
+ */
+pub struct PipeConnector {
+    clients: mpsc::Sender<PipeStream>,
+}
+
+pub struct PipeAcceptor {
+    clients: mpsc::Receiver<PipeStream>,
+}
+
+/**
+ * Create a connector and acceptor that talk over in-memory pipes. Every
+ * connection made by the connector is handed to the acceptor.
+ */
+pub fn pipe_transport() -> (PipeConnector, PipeAcceptor) {
+    let (clients, incoming) = mpsc::channel();
+    return (
+        PipeConnector { clients },
+        PipeAcceptor { clients: incoming },
+    );
+}
+
+impl Connector for PipeConnector {
+    type Stream = PipeStream;
+
+    fn connect(&mut self) -> io::Result<PipeStream> {
+        let (ours, theirs) = pipe();
+        self.clients.send(theirs).map_err(|_| io::Error::new(
+            ErrorKind::ConnectionRefused,
+            "the acceptor is gone",
+        ))?;
+        return Ok(ours);
+    }
+
+    fn describe(&self) -> String {
+        return String::from("pipe");
+    }
+}
+
+impl Acceptor for PipeAcceptor {
+    type Stream = PipeStream;
+
+    fn accept(&mut self) -> io::Result<PipeStream> {
+        return self.clients.recv().map_err(|_| io::Error::new(
+            ErrorKind::ConnectionAborted,
+            "the connector is gone",
+        ));
+    }
+
+    fn try_accept(&mut self) -> io::Result<Option<PipeStream>> {
+        return match self.clients.try_recv() {
+            Ok(stream) => Ok(Some(stream)),
+            Err(mpsc::TryRecvError::Empty) => Ok(None),
+            Err(mpsc::TryRecvError::Disconnected) => Err(io::Error::new(
+                ErrorKind::ConnectionAborted,
+                "the connector is gone",
+            )),
+        };
+    }
+
+    fn describe(&self) -> String {
+        return String::from("pipe");
+    }
+}
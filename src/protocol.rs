@@ -0,0 +1,250 @@
+use chess::Square;
+use chess_network_protocol::*;
+
+use crate::chess_engine::*;
+use crate::local_engine::{IllegalMove, LocalGame, Rules};
+
+/*
+ * Conversions between the engine types and chess-network-protocol, for
+ * everything that referees games with a [LocalGame].
+ */
+
+pub fn local_piece_to_proto(piece: chess::Piece) -> Piece {
+    use chess::Player::*;
+
+    return match (piece.player, piece.kind.name) {
+        (White, "Q") => Piece::WhiteQueen,
+        (White, "K") => Piece::WhiteKing,
+        (White, "R") => Piece::WhiteRook,
+        (White, "N") => Piece::WhiteKnight,
+        (White, "B") => Piece::WhiteBishop,
+        (White, "P") => Piece::WhitePawn,
+
+        (Black, "Q") => Piece::BlackQueen,
+        (Black, "K") => Piece::BlackKing,
+        (Black, "R") => Piece::BlackRook,
+        (Black, "N") => Piece::BlackKnight,
+        (Black, "B") => Piece::BlackBishop,
+        (Black, "P") => Piece::BlackPawn,
+        _ => Piece::None,
+    };
+}
+
+/**
+ * The piece of `white` (or black) called `name` that a pawn can promote to,
+ * [Piece::None] if there is none.
+ */
+fn promotion_to_proto(name: &str, white: bool) -> Piece {
+    return match (white, name) {
+        (true, "Q") => Piece::WhiteQueen,
+        (true, "R") => Piece::WhiteRook,
+        (true, "B") => Piece::WhiteBishop,
+        (true, "N") => Piece::WhiteKnight,
+
+        (false, "Q") => Piece::BlackQueen,
+        (false, "R") => Piece::BlackRook,
+        (false, "B") => Piece::BlackBishop,
+        (false, "N") => Piece::BlackKnight,
+        _ => Piece::None,
+    };
+}
+
+/**
+ * The color ([true] for white) and name of a piece a pawn can promote to.
+ */
+fn proto_to_promotion(piece: Piece) -> Option<(bool, &'static str)> {
+    return match piece {
+        Piece::WhiteQueen => Some((true, "Q")),
+        Piece::WhiteRook => Some((true, "R")),
+        Piece::WhiteBishop => Some((true, "B")),
+        Piece::WhiteKnight => Some((true, "N")),
+
+        Piece::BlackQueen => Some((false, "Q")),
+        Piece::BlackRook => Some((false, "R")),
+        Piece::BlackBishop => Some((false, "B")),
+        Piece::BlackKnight => Some((false, "N")),
+        _ => None,
+    };
+}
+
+/**
+ * Convert a move made by `white` (or black). Promotions are always to a queen.
+ */
+pub fn chess_move_to_proto(mv: &ChessMove, white: bool) -> Move {
+    return Move {
+        start_x: mv.from.0 as usize,
+        start_y: mv.from.1 as usize,
+        end_x: mv.to.0 as usize,
+        end_y: mv.to.1 as usize,
+        promotion: if mv.promotion {
+            if white {
+                Piece::WhiteQueen
+            } else {
+                Piece::BlackQueen
+            }
+        } else {
+            Piece::None
+        },
+    };
+}
+
+/**
+ * Return [true] if both squares of `mv` are on the board. Moves from a peer
+ * have to be checked before they are used as board indices.
+ */
+pub fn move_on_board(mv: &Move) -> bool {
+    return [mv.start_x, mv.start_y, mv.end_x, mv.end_y]
+        .iter()
+        .all(|&coord| coord < 8);
+}
+
+/**
+ * Name of the square on file `x` and rank `y` in algebraic notation, e.g.
+ * `e4`. Squares off the board, which peers may send, come out as `(x, y)`.
+ */
+pub fn square_name(x: usize, y: usize) -> String {
+    if x >= 8 || y >= 8 {
+        return format!("({}, {})", x, y);
+    }
+    return format!("{}{}", (b'a' + x as u8) as char, y + 1);
+}
+
+/**
+ * Like [square_name], for a location on the board.
+ */
+pub fn loc_name(loc: &ChessLoc) -> String {
+    return match (usize::try_from(loc.0), usize::try_from(loc.1)) {
+        (Ok(x), Ok(y)) => square_name(x, y),
+        _ => format!("({}, {})", loc.0, loc.1),
+    };
+}
+
+/**
+ * Write a move in coordinate notation like `e2e4`, see [square_name].
+ */
+pub fn move_name(mv: &Move) -> String {
+    return format!("{}{}", square_name(mv.start_x, mv.start_y),
+                   square_name(mv.end_x, mv.end_y));
+}
+
+/**
+ * Convert a move, [None] if it is off the board.
+ */
+pub fn proto_to_chess_move(mv: &Move) -> Option<ChessMove> {
+    if !move_on_board(mv) {
+        return None;
+    }
+
+    let from = (mv.start_x as i32, mv.start_y as i32);
+    let to = (mv.end_x as i32, mv.end_y as i32);
+    return Some(ChessMove {
+        from,
+        to,
+        /* NOTE: this is only used to apply moves to the engine, which does not
+         * look at it */
+        capture: false,
+        promotion: mv.promotion != Piece::None,
+    });
+}
+
+pub fn proto_board(engine: &LocalGame) -> [[Piece; 8]; 8] {
+    let board = engine.get_board();
+    let mut out = [[Piece::None; 8]; 8];
+    for y in 0..8 {
+        for x in 0..8 {
+            if let Square::Occupied(piece) = board[8*y + x] {
+                out[y][x] = local_piece_to_proto(piece);
+            }
+        }
+    }
+
+    return out;
+}
+
+/**
+ * All moves of the player to move, with a move for every piece a pawn can
+ * promote to.
+ */
+pub fn proto_moves(engine: &LocalGame) -> Vec<Move> {
+    let white = engine.get_player();
+    return engine.get_all_promotions().iter()
+        .map(|(mv, promotion)| Move {
+            promotion: promotion.map_or(Piece::None,
+                                        |name| promotion_to_proto(name, white)),
+            ..chess_move_to_proto(mv, white)
+        })
+        .collect();
+}
+
+/**
+ * Play `mv` in `engine`, promoting to exactly the piece it asks for. Returns
+ * [false] if it is not a legal move.
+ */
+pub fn apply_proto_move(engine: &mut LocalGame, mv: &Move) -> bool {
+    let chess_mv = match proto_to_chess_move(mv) {
+        Some(chess_mv) => chess_mv,
+        None => return false,
+    };
+    if mv.promotion == Piece::None {
+        return engine.apply_promotion(&chess_mv, None);
+    }
+
+    return match proto_to_promotion(mv.promotion) {
+        Some((white, name)) if white == engine.get_player()
+            => engine.apply_promotion(&chess_mv, Some(name)),
+        _ => false,
+    };
+}
+
+/**
+ * Check `mv` against the moves of the player to move, explaining why it is
+ * refused if it is. Whose turn it is and whether the game is over is up to
+ * the caller.
+ */
+pub fn check_proto_move(engine: &LocalGame, mv: &Move)
+    -> Result<(), IllegalMove> {
+    if !move_on_board(mv) {
+        return Err(IllegalMove::OffBoard);
+    }
+    let moves = proto_moves(engine);
+    if moves.contains(mv) {
+        return Ok(());
+    }
+
+    let squares = |mv: &Move| (mv.start_x, mv.start_y, mv.end_x, mv.end_y);
+    if moves.iter().any(|legal| squares(legal) == squares(mv)) {
+        return Err(IllegalMove::BadPromotion);
+    }
+
+    let from = (mv.start_x as i32, mv.start_y as i32);
+    let to = (mv.end_x as i32, mv.end_y as i32);
+    return Err(engine.why_illegal(from, to));
+}
+
+/**
+ * What to advertise in the handshake for a game played by `rules`.
+ */
+pub fn proto_features(rules: Rules) -> Vec<Features> {
+    let mut features = Vec::new();
+    if rules.en_passant {
+        features.push(Features::EnPassant);
+    }
+    if rules.castling {
+        features.push(Features::Castling);
+    }
+    features.push(Features::Promotion);
+
+    return features;
+}
+
+pub fn proto_joever(state: ChessState) -> Joever {
+    use ChessState::*;
+
+    return match state {
+        Ongoing => Joever::Ongoing,
+        JoeverIndeterminate => Joever::Indeterminate,
+        JoeverDraw => Joever::Draw,
+        JoeverWhite => Joever::White,
+        JoeverBlack => Joever::Black,
+    };
+}
@@ -0,0 +1,321 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::connection::JsonReader;
+use crate::transport::{Acceptor, Connector, Nonblocking};
+
+/*
+ * Recordings are JSON Lines files with one [Record] per message, taken from
+ * the bytes going through a transport's streams. They can be played back to
+ * a peer with [replay].
+ */
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/**
+ * One message as it went over the connection.
+ */
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Record {
+    /// Milliseconds since the recording started
+    pub time_ms: u64,
+    /// Numbers the connections in the order they were made, from 0
+    pub connection: usize,
+    pub direction: Direction,
+    /// The message, [Value::Null] if it was not valid JSON
+    pub message: Value,
+    /// What was sent instead of valid JSON
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invalid: Option<String>,
+}
+
+struct Log {
+    file: BufWriter<File>,
+    start: Instant,
+    connections: usize,
+}
+
+/**
+ * Writes the [Record]s of all connections to one file. Clones write to the
+ * same file.
+ */
+#[derive(Clone)]
+pub struct Recorder {
+    log: Arc<Mutex<Log>>,
+}
+
+impl Recorder {
+    /**
+     * Start a recording in `path`, replacing the file if it exists.
+     */
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let log = Log {
+            file: BufWriter::new(File::create(path)?),
+            start: Instant::now(),
+            connections: 0,
+        };
+        return Ok(Recorder { log: Arc::new(Mutex::new(log)) });
+    }
+
+    /**
+     * Record everything going through `stream` as a new connection.
+     */
+    pub fn wrap<S>(&self, stream: S) -> RecordingStream<S> {
+        let mut log = self.log.lock().unwrap();
+        let connection = log.connections;
+        log.connections += 1;
+
+        return RecordingStream {
+            inner: stream,
+            recorder: Some(self.clone()),
+            connection,
+            sent: JsonReader::default(),
+            received: JsonReader::default(),
+        };
+    }
+
+    fn record(&self, record: &Record) {
+        let mut log = self.log.lock().unwrap();
+        let written = serde_json::to_writer(&mut log.file, record)
+            .map_err(io::Error::from)
+            .and_then(|_| log.file.write_all(b"\n"))
+            .and_then(|_| log.file.flush());
+        if let Err(e) = written {
+            eprintln!("Could not record message: {}", e);
+        }
+    }
+
+    /**
+     * Record the complete messages in `reader`.
+     */
+    fn record_all(&self, connection: usize, direction: Direction,
+                  reader: &mut JsonReader) {
+        let time_ms = {
+            let log = self.log.lock().unwrap();
+            log.start.elapsed().as_millis() as u64
+        };
+        let record = |message, invalid| Record {
+            time_ms, connection, direction, message, invalid,
+        };
+
+        loop {
+            match reader.parse::<Value>() {
+                Ok(Some(message)) => self.record(&record(message, None)),
+                Ok(None) => return,
+                Err(_) => {
+                    let junk = reader.take_junk();
+                    let junk = String::from_utf8_lossy(&junk).into_owned();
+                    self.record(&record(Value::Null, Some(junk)));
+                },
+            }
+        }
+    }
+}
+
+/**
+ * A stream whose messages are recorded, if there is a [Recorder].
+ */
+pub struct RecordingStream<S> {
+    inner: S,
+    recorder: Option<Recorder>,
+    connection: usize,
+    sent: JsonReader,
+    received: JsonReader,
+}
+
+impl<S: Read> Read for RecordingStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(recorder) = &self.recorder {
+            self.received.extend(&buf[..n]);
+            recorder.record_all(self.connection, Direction::Received,
+                                &mut self.received);
+        }
+        return Ok(n);
+    }
+}
+
+impl<S: Write> Write for RecordingStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        if let Some(recorder) = &self.recorder {
+            self.sent.extend(&buf[..n]);
+            recorder.record_all(self.connection, Direction::Sent,
+                                &mut self.sent);
+        }
+        return Ok(n);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return self.inner.flush();
+    }
+}
+
+impl<S: Nonblocking> Nonblocking for RecordingStream<S> {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        return self.inner.set_nonblocking(nonblocking);
+    }
+}
+
+fn wrap<S>(recorder: &Option<Recorder>, stream: S) -> RecordingStream<S> {
+    return match recorder {
+        Some(recorder) => recorder.wrap(stream),
+        None => RecordingStream {
+            inner: stream,
+            recorder: None,
+            connection: 0,
+            sent: JsonReader::default(),
+            received: JsonReader::default(),
+        },
+    };
+}
+
+/**
+ * Records the connections made by another [Connector], or passes them through
+ * untouched without a [Recorder].
+ */
+pub struct RecordingConnector<C: Connector> {
+    inner: C,
+    recorder: Option<Recorder>,
+}
+
+impl<C: Connector> RecordingConnector<C> {
+    pub fn new(inner: C, recorder: Option<Recorder>) -> Self {
+        return RecordingConnector { inner, recorder };
+    }
+}
+
+impl<C: Connector> Connector for RecordingConnector<C> {
+    type Stream = RecordingStream<C::Stream>;
+
+    fn connect(&mut self) -> io::Result<Self::Stream> {
+        return Ok(wrap(&self.recorder, self.inner.connect()?));
+    }
+
+    fn describe(&self) -> String {
+        return self.inner.describe();
+    }
+}
+
+/**
+ * Records the connections accepted by another [Acceptor], see
+ * [RecordingConnector].
+ */
+pub struct RecordingAcceptor<A: Acceptor> {
+    inner: A,
+    recorder: Option<Recorder>,
+}
+
+impl<A: Acceptor> RecordingAcceptor<A> {
+    pub fn new(inner: A, recorder: Option<Recorder>) -> Self {
+        return RecordingAcceptor { inner, recorder };
+    }
+}
+
+impl<A: Acceptor> Acceptor for RecordingAcceptor<A> {
+    type Stream = RecordingStream<A::Stream>;
+
+    fn accept(&mut self) -> io::Result<Self::Stream> {
+        return Ok(wrap(&self.recorder, self.inner.accept()?));
+    }
+
+    fn try_accept(&mut self) -> io::Result<Option<Self::Stream>> {
+        let stream = self.inner.try_accept()?;
+        return Ok(stream.map(|stream| wrap(&self.recorder, stream)));
+    }
+
+    fn describe(&self) -> String {
+        return self.inner.describe();
+    }
+}
+
+/**
+ * Read a recording made by a [Recorder].
+ */
+pub fn load(path: impl AsRef<Path>) -> io::Result<Vec<Record>> {
+    let mut records = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            records.push(serde_json::from_str(&line)?);
+        }
+    }
+
+    return Ok(records);
+}
+
+/**
+ * Return [true] if `connection` in the recording was made by a client, which
+ * always sends the first message.
+ */
+pub fn recorded_by_client(records: &[Record], connection: usize) -> bool {
+    return records.iter()
+        .find(|record| record.connection == connection)
+        .is_some_and(|record| record.direction == Direction::Sent);
+}
+
+/**
+ * Play the other side of `connection` in a recording to `stream`: what was
+ * received is sent, and what was sent is waited for, up to `timeout` each,
+ * and compared with what arrives. Returns the number of messages that
+ * differed from the recording, each is also printed.
+ */
+pub fn replay<S: Read + Write>(
+    records: &[Record],
+    connection: usize,
+    stream: &mut S,
+    timeout: Duration,
+) -> io::Result<usize> {
+    let mut reader = JsonReader::default();
+    let mut mismatches = 0;
+
+    for record in records.iter().filter(|r| r.connection == connection) {
+        match record.direction {
+            Direction::Received => {
+                match &record.invalid {
+                    Some(junk) => {
+                        stream.write_all(junk.as_bytes())?;
+                        println!("-> (invalid) {}", junk);
+                    },
+                    None => {
+                        serde_json::to_writer(&mut *stream, &record.message)?;
+                        println!("-> {}", record.message);
+                    },
+                }
+                stream.flush()?;
+            },
+            Direction::Sent => {
+                let message: Value = match reader.read(stream, timeout) {
+                    Ok(message) => message,
+                    /* the peer sent something invalid again */
+                    Err(e) if e.kind() == io::ErrorKind::InvalidData
+                        && record.invalid.is_some() => {
+                        let junk = reader.take_junk();
+                        println!("<- (invalid) {}",
+                                 String::from_utf8_lossy(&junk));
+                        continue;
+                    },
+                    Err(e) => return Err(e),
+                };
+                println!("<- {}", message);
+                if message != record.message {
+                    mismatches += 1;
+                    println!("   expected {}", record.message);
+                }
+            },
+        }
+    }
+
+    return Ok(mismatches);
+}
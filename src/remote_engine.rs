@@ -1,9 +1,31 @@
-use std::{collections::HashMap, net::TcpStream};
-use serde::de::Deserialize;
+use std::collections::HashMap;
+use std::io::Write;
+use std::mem::discriminant;
+
+use serde::{Deserialize, Serialize};
 
 use crate::chess_engine::*;
+use crate::connection::{JsonReader, RESPONSE_TIMEOUT};
+use crate::local_engine::{LocalGame, Rules};
+use crate::protocol::*;
+use crate::transport::Connector;
 use chess_network_protocol::*;
 
+/**
+ * What to do when the server's state does not match our own engine's, see
+ * [RemoteGame::verify].
+ */
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Verification {
+    /// Trust the server
+    Off,
+    /// Tell the user and stop verifying
+    Warn,
+    /// Tell the user and end the game
+    Abort,
+}
+
 fn parse_piece(piece: &Piece) -> (bool, String) {
     use Piece::*;
 
@@ -24,47 +46,366 @@ fn parse_piece(piece: &Piece) -> (bool, String) {
     }
 }
 
-pub struct RemoteGame {
-    stream: TcpStream,
+fn feature_name(feature: &Features) -> String {
+    return match feature {
+        Features::EnPassant => String::from("En passant"),
+        Features::Castling => String::from("Castling"),
+        Features::Promotion => String::from("Promotion"),
+        Features::Stalemate => String::from("Stalemate"),
+        Features::PossibleMoveGeneration => String::from("Move generation"),
+        Features::Other(name) => name.clone(),
+    };
+}
+
+/**
+ * Return [true] if `features` contains `feature`, ignoring the name of
+ * [Features::Other].
+ */
+fn has_feature(features: &[Features], feature: &Features) -> bool {
+    let wanted = discriminant(feature);
+    return features.iter().any(|f| discriminant(f) == wanted);
+}
+
+/**
+ * The moves in a form that can be compared, the promotion piece is left out
+ * since servers differ in which ones they list.
+ */
+fn comparable_moves(moves: &[Move]) -> Vec<(usize, usize, usize, usize, bool)> {
+    let mut moves: Vec<_> = moves.iter()
+        .map(|mv| (mv.start_x, mv.start_y, mv.end_x, mv.end_y,
+                   mv.promotion != Piece::None))
+        .collect();
+    moves.sort();
+    moves.dedup();
+    return moves;
+}
+
+/**
+ * Describe the standard rules missing from `features`, [None] if there are
+ * none.
+ */
+fn missing_rules(features: &[Features]) -> Option<String> {
+    let missing: Vec<_> = [Features::Castling, Features::EnPassant]
+        .iter()
+        .filter(|f| !has_feature(features, f))
+        .map(|f| feature_name(f).to_lowercase())
+        .collect();
+    if missing.is_empty() {
+        return None;
+    }
+    return Some(format!("The server does not support {}",
+                        missing.join(" or ")));
+}
+
+pub struct RemoteGame<C: Connector> {
+    connector: C,
+    stream: C::Stream,
+    reader: JsonReader,
     moves: Vec<Move>,
     board: [[Piece; 8]; 8],
     joever: Joever,
     color: Color,
+    /// What the server said it supports in the handshake
+    features: Vec<Features>,
     waiting: bool,
+    /// Only watching, see [RemoteGame::spectate]
+    spectating: bool,
+    message: Option<String>,
+    connected: bool,
+    verification: Verification,
+    /// Our own copy of the game while verifying the server
+    shadow: Option<LocalGame>,
+    /// Set once verification ended the game, which then cannot reconnect
+    aborted: bool,
+}
+
+fn off_board() -> std::io::Error {
+    return std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "the server sent a move off the board",
+    );
 }
 
-impl RemoteGame {
-    pub fn new(ip: &String) -> std::io::Result<Self> {
-        let stream = TcpStream::connect(ip)?;
-        let mut de = serde_json::Deserializer::from_reader(&stream);
-        let server_color = Color::Black;
+/**
+ * Fail if `msg` holds a move off the board, see [move_on_board].
+ */
+fn check_squares(msg: ServerToClient) -> std::io::Result<ServerToClient> {
+    let on_board = match &msg {
+        ServerToClient::State { moves, move_made, .. } => {
+            move_on_board(move_made) && moves.iter().all(move_on_board)
+        },
+        ServerToClient::Error { moves, .. }
+            | ServerToClient::Draw { moves, .. }
+            => moves.iter().all(move_on_board),
+        ServerToClient::Resigned { .. } => true,
+    };
+    if !on_board {
+        return Err(off_board());
+    }
+    return Ok(msg);
+}
 
-        let handshake = ClientToServerHandshake {
-            server_color,
-        };
-        serde_json::to_writer(&stream, &handshake)?;
+/**
+ * Open a connection to the server and do the handshake.
+ */
+fn connect<C: Connector>(connector: &mut C, color: Color)
+    -> std::io::Result<(C::Stream, JsonReader, ServerToClientHandshake)> {
+    let mut stream = connector.connect()?;
+    let mut reader = JsonReader::default();
+    let server_color = if color == Color::White {
+        Color::Black
+    } else {
+        Color::White
+    };
 
-        let s2ch = ServerToClientHandshake::deserialize(&mut de)?;
-        println!("Received s2ch: {:?}", s2ch);
+    let handshake = ClientToServerHandshake {
+        server_color,
+    };
+    serde_json::to_writer(&mut stream, &handshake)?;
+    stream.flush()?;
+
+    let s2ch: ServerToClientHandshake
+        = reader.read(&mut stream, RESPONSE_TIMEOUT)?;
+    if !s2ch.moves.iter().all(move_on_board) {
+        return Err(off_board());
+    }
+    return Ok((stream, reader, s2ch));
+}
+
+impl<C: Connector> RemoteGame<C> {
+    /**
+     * Connect to the server through `connector` and play as `color`.
+     */
+    pub fn new(mut connector: C, color: Color) -> std::io::Result<Self> {
+        let (stream, reader, s2ch) = connect(&mut connector, color)?;
 
         return Ok(RemoteGame {
+            connector,
             stream,
+            reader,
             moves: s2ch.moves,
             board: s2ch.board,
             joever: s2ch.joever,
-            color: if server_color == Color::Black {
-                Color::White
-            } else {
-                Color::Black
+            color,
+            message: missing_rules(&s2ch.features),
+            features: s2ch.features,
+            /* the server makes the first move */
+            waiting: color == Color::Black,
+            spectating: false,
+            connected: true,
+            verification: Verification::Off,
+            shadow: None,
+            aborted: false,
+        });
+    }
+
+    /**
+     * Replay every move the server reports in an engine of our own and
+     * compare the board, the moves and the result with the server's. Only
+     * possible for games joined before any move was made, e.g. not when
+     * spectating a game under way.
+     */
+    pub fn verify(&mut self, verification: Verification) {
+        self.verification = verification;
+        if verification == Verification::Off {
+            self.shadow = None;
+            return;
+        }
+
+        let shadow = LocalGame::with_rules(Rules {
+            castling: self.supports(&Features::Castling),
+            en_passant: self.supports(&Features::EnPassant),
+        });
+        /* the moves that led to any other board are unknown */
+        if proto_board(&shadow) != self.board {
+            self.verification = Verification::Off;
+            self.shadow = None;
+            let note = "The game is already under way, it cannot be verified";
+            self.message = Some(match self.message.take() {
+                Some(message) => format!("{}. {}", message, note),
+                None => String::from(note),
+            });
+            return;
+        }
+
+        self.shadow = Some(shadow);
+        self.check_shadow(None);
+    }
+
+    /**
+     * Compare the server's state with ours after `move_made`, which is played
+     * in our engine first. Returns what is wrong, if anything.
+     */
+    fn shadow_problem(&mut self, move_made: Option<&Move>) -> Option<String> {
+        /* servers without move generation may leave the moves out */
+        let generates_moves = self.supports(&Features::PossibleMoveGeneration)
+            || !self.moves.is_empty();
+        let shadow = self.shadow.as_mut()?;
+
+        if let Some(mv) = move_made {
+            if let Err(reason) = check_proto_move(shadow, mv) {
+                return Some(format!("{} is not a legal move: {}",
+                                    move_name(mv), reason));
+            }
+            apply_proto_move(shadow, mv);
+        }
+
+        if proto_board(shadow) != self.board {
+            return Some(String::from("the board is wrong"));
+        }
+        if generates_moves && comparable_moves(&proto_moves(shadow))
+                != comparable_moves(&self.moves) {
+            return Some(String::from("the legal moves are wrong"));
+        }
+        if discriminant(&proto_joever(shadow.get_state()))
+            != discriminant(&self.joever) {
+            return Some(String::from("the result is wrong"));
+        }
+
+        return None;
+    }
+
+    /**
+     * See [RemoteGame::shadow_problem], reporting problems as the
+     * verification mode asks for.
+     */
+    fn check_shadow(&mut self, move_made: Option<&Move>) {
+        let problem = match self.shadow_problem(move_made) {
+            Some(problem) => problem,
+            None => return,
+        };
+        let after = match move_made {
+            Some(mv) => format!(" after {}", move_name(mv)),
+            None => String::new(),
+        };
+
+        /* our engine no longer follows the game either way */
+        self.shadow = None;
+        if self.verification == Verification::Abort {
+            self.aborted = true;
+            self.connected = false;
+            self.message = Some(format!(
+                "Game aborted, the server cannot be trusted: {}{}",
+                problem, after,
+            ));
+        } else {
+            self.message = Some(format!(
+                "The server cannot be trusted: {}{}. No longer verifying",
+                problem, after,
+            ));
+        }
+    }
+
+    /**
+     * Watch a game through `connector`, which has to lead to the server's
+     * spectator port. No moves can be made, every move is waited for.
+     */
+    pub fn spectate(connector: C) -> std::io::Result<Self> {
+        let mut game = RemoteGame::new(connector, Color::White)?;
+        game.waiting = true;
+        game.spectating = true;
+        return Ok(game);
+    }
+
+    /**
+     * Send a message to the server.
+     */
+    fn send(&mut self, msg: &ClientToServer) -> std::io::Result<()> {
+        serde_json::to_writer(&mut self.stream, msg)?;
+        self.stream.flush()?;
+        return Ok(());
+    }
+
+    /**
+     * Mark the connection as lost, telling the user why.
+     */
+    fn disconnect(&mut self, reason: impl std::fmt::Display) {
+        self.connected = false;
+        self.message = Some(format!("Connection lost: {}", reason));
+    }
+
+    /**
+     * Take over the state sent by the server. Returns the server's message if
+     * it sent an error instead.
+     */
+    fn receive(&mut self, msg: ServerToClient) -> Option<String> {
+        return match msg {
+            ServerToClient::State { board, moves, joever, move_made } => {
+                self.board = board;
+                self.moves = moves;
+                self.joever = joever;
+                self.check_shadow(Some(&move_made));
+                None
+            },
+            ServerToClient::Error { board, moves, joever, message } => {
+                self.board = board;
+                self.moves = moves;
+                self.joever = joever;
+                self.check_shadow(None);
+                Some(message)
+            },
+            ServerToClient::Resigned { board, joever } => {
+                self.board = board;
+                self.joever = joever;
+                self.message = Some(String::from("The opponent resigned"));
+                None
+            },
+            ServerToClient::Draw { board, moves } => {
+                self.board = board;
+                self.moves = moves;
+                self.joever = Joever::Draw;
+                self.message = Some(String::from("The game ended in a draw"));
+                None
             },
-            waiting: false,
+        };
+    }
+
+    fn supports(&self, feature: &Features) -> bool {
+        return has_feature(&self.features, feature);
+    }
+
+    /**
+     * Pick the piece to promote to when moving from `from` to `to`: a queen if
+     * the server allows it, otherwise the first piece it offers.
+     */
+    fn promotion_piece(&self, from: ChessLoc, to: ChessLoc) -> Option<Piece> {
+        let offered: Vec<Piece> = self.moves.iter()
+            .filter(|mv| (mv.start_x as i32, mv.start_y as i32) == from
+                && (mv.end_x as i32, mv.end_y as i32) == to)
+            .map(|mv| mv.promotion)
+            .collect();
+        let queen = if self.color == Color::White {
+            Piece::WhiteQueen
+        } else {
+            Piece::BlackQueen
+        };
+
+        if offered.contains(&queen) {
+            return Some(queen);
+        }
+        return offered.into_iter().find(|piece| *piece != Piece::None);
+    }
+
+    /**
+     * Return [true] if the moves sent by the server are ours to make. The
+     * server only sends the moves of the side to move.
+     */
+    fn our_move(&self) -> bool {
+        let white = self.color == Color::White;
+        return self.moves.iter().any(|mv| {
+            let (piece_white, name)
+                = parse_piece(&self.board[mv.start_y][mv.start_x]);
+            piece_white == white && name != " "
         });
     }
 }
 
-impl ChessGame for RemoteGame {
+impl<C: Connector> ChessGame for RemoteGame<C> {
     fn get_moves(&mut self, loc: &ChessLoc) -> HashMap<ChessLoc, ChessMove> {
         let mut map: HashMap<ChessLoc, ChessMove> = HashMap::new();
+        if self.spectating {
+            return map;
+        }
         for mv in self.moves.iter() {
             if *loc != (mv.start_x as i32, mv.start_y as i32) {
                 continue;
@@ -75,7 +416,8 @@ impl ChessGame for RemoteGame {
             map.insert(to, ChessMove {
                 from, to,
                 capture: self.board[mv.end_y][mv.end_x] != Piece::None,
-                promotion: mv.promotion != Piece::None,
+                promotion: mv.promotion != Piece::None
+                    && self.supports(&Features::Promotion),
             });
         }
 
@@ -83,7 +425,15 @@ impl ChessGame for RemoteGame {
     }
 
     fn apply_move(&mut self, mv: &ChessMove) -> bool {
-        let promotion = if match self.board[mv.from.1 as usize][mv.from.0 as usize] {
+        if !self.connected || self.spectating {
+            return false;
+        }
+
+        let promotion = if !self.supports(&Features::Promotion) {
+            Piece::None
+        } else if let Some(piece) = self.promotion_piece(mv.from, mv.to) {
+            piece
+        } else if match self.board[mv.from.1 as usize][mv.from.0 as usize] {
             Piece::BlackPawn => true,
             Piece::WhitePawn => true,
             _ => false,
@@ -105,65 +455,127 @@ impl ChessGame for RemoteGame {
             promotion,
         });
 
-        match serde_json::to_writer(&self.stream, &mv2) {
-            Err(_) => return false,
-            _ => (),
-        };
-
-        let mut de = serde_json::Deserializer::from_reader(&self.stream);
+        if let Err(e) = self.send(&mv2) {
+            self.disconnect(e);
+            return false;
+        }
 
-        println!("<Waiting legal");
-        let is_legal = match ServerToClient::deserialize(&mut de) {
+        let answer = self.reader.read(&mut self.stream, RESPONSE_TIMEOUT)
+            .and_then(check_squares);
+        let is_legal: ServerToClient = match answer {
             Ok(a) => a,
-            _ => return false,
+            Err(e) => {
+                self.disconnect(e);
+                return false;
+            },
         };
-        println!("{is_legal:?}");
-        println!(">Received legal");
-
-        return match is_legal {
-            ServerToClient::State { board, moves, joever, move_made: _ } => {
+        return match self.receive(is_legal) {
+            None => {
                 self.waiting = true;
-                self.board = board;
-                self.moves = moves;
-                self.joever = joever;
                 true
             },
-            ServerToClient::Error { board, moves, joever, message } => {
-                self.board = board;
-                self.moves = moves;
-                self.joever = joever;
-                println!("Illegal! {}", message);
+            Some(message) => {
+                self.message = Some(format!("Illegal move: {}", message));
                 false
             },
-            _ => false,
         };
     }
 
     fn wait_move(&mut self) -> bool {
-        if !self.waiting {
+        if !self.waiting || !self.connected {
             return false;
         }
-        self.waiting = false;
 
-        let mut de = serde_json::Deserializer::from_reader(&self.stream);
-
-        println!("<Waiting server_move");
-        let server_move = match ServerToClient::deserialize(&mut de) {
-            Ok(a) => a,
-            _ => return false,
+        let polled = self.reader.poll(&mut self.stream)
+            .and_then(|msg| msg.map(check_squares).transpose());
+        let server_move: ServerToClient = match polled {
+            Ok(Some(a)) => a,
+            /* the opponent is still thinking */
+            Ok(None) => return false,
+            Err(e) => {
+                self.disconnect(e);
+                return false;
+            },
         };
-        println!("{server_move:?}");
-        println!(">Received server_move");
-
-        return match server_move {
-            ServerToClient::State { board, moves, joever, move_made: _ } => {
-                self.board = board;
-                self.moves = moves;
-                self.joever = joever;
+        return match self.receive(server_move) {
+            None => {
+                self.waiting = self.spectating;
                 true
             },
-            _ => false,
+            Some(message) => {
+                /* the opponent still has to move */
+                self.message = Some(format!("Server error: {}", message));
+                false
+            },
+        };
+    }
+
+    fn reconnect(&mut self) -> std::io::Result<bool> {
+        if self.aborted {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "the game was aborted, the server cannot be trusted",
+            ));
+        }
+
+        let (stream, reader, s2ch) = connect(&mut self.connector, self.color)?;
+        self.stream = stream;
+        self.reader = reader;
+        self.board = s2ch.board;
+        self.moves = s2ch.moves;
+        self.joever = s2ch.joever;
+        self.features = s2ch.features;
+        self.connected = true;
+        /* moves made while we were gone cannot be checked */
+        let in_sync = self.shadow.as_ref()
+            .is_some_and(|shadow| proto_board(shadow) == self.board);
+        if self.shadow.is_some() && !in_sync {
+            self.shadow = None;
+            self.message = Some(String::from(
+                "Missed moves while disconnected, no longer verifying",
+            ));
+        }
+        self.waiting = self.spectating || (!self.our_move()
+            && matches!(self.joever, Joever::Ongoing));
+
+        return Ok(self.waiting);
+    }
+
+    fn opponent_starts(&self) -> bool {
+        return self.color == Color::Black || self.spectating;
+    }
+
+    fn is_spectating(&self) -> bool {
+        return self.spectating;
+    }
+
+    fn rules(&self) -> Vec<String> {
+        let mut rules = vec![String::from("Server features:")];
+        for feature in [
+            Features::Castling,
+            Features::EnPassant,
+            Features::Promotion,
+            Features::Stalemate,
+            Features::PossibleMoveGeneration,
+        ] {
+            let supported = if self.supports(&feature) { "yes" } else { "no" };
+            rules.push(format!("{}: {}", feature_name(&feature), supported));
+        }
+        for feature in &self.features {
+            if let Features::Other(name) = feature {
+                rules.push(name.clone());
+            }
         }
+
+        return rules;
+    }
+
+    fn take_message(&mut self) -> Option<String> {
+        return self.message.take();
+    }
+
+    fn is_connected(&self) -> bool {
+        return self.connected;
     }
 
     fn get_piece(&mut self, loc: &ChessLoc) -> (bool, String) {
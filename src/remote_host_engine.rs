@@ -1,216 +1,439 @@
-use std::{collections::HashMap, net::{TcpStream, TcpListener}};
-use serde::de::Deserialize;
+use std::collections::HashMap;
+use std::io::{ErrorKind, Write};
+
+use serde::Serialize;
 
 use crate::chess_engine::*;
-use crate::local_engine::LocalGame;
+use crate::connection::{JsonReader, PendingHandshake, RESPONSE_TIMEOUT};
+use crate::local_engine::{IllegalMove, LocalGame, Rules};
+use crate::protocol::*;
+use crate::spectators::Spectators;
+use crate::transport::Acceptor;
 use chess_network_protocol::*;
-use chess::Square;
-
-fn local_piece_to_proto(piece: chess::Piece) -> Piece {
-    use chess::Player::*;
-
-    return match (piece.player, piece.kind.name) {
-        (White, "Q") => Piece::WhiteQueen,
-        (White, "K") => Piece::WhiteKing,
-        (White, "R") => Piece::WhiteRook,
-        (White, "N") => Piece::WhiteKnight,
-        (White, "B") => Piece::WhiteBishop,
-        (White, "P") => Piece::WhitePawn,
-
-        (Black, "Q") => Piece::BlackQueen,
-        (Black, "K") => Piece::BlackKing,
-        (Black, "R") => Piece::BlackRook,
-        (Black, "N") => Piece::BlackKnight,
-        (Black, "B") => Piece::BlackBishop,
-        (Black, "P") => Piece::BlackPawn,
-        _ => Piece::None,
-    };
-}
 
-pub struct RemoteHostGame {
-    stream: TcpStream,
+/// How many invalid messages or illegal moves in a row a client may send
+/// before it is cut off
+pub const MAX_STRIKES: usize = 10;
+
+pub struct RemoteHostGame<A: Acceptor> {
+    acceptor: A,
+    stream: A::Stream,
+    reader: JsonReader,
     engine: LocalGame,
     server_color: Color,
-    last_client_move: Move,
+    resigned: bool,
+    message: Option<String>,
+    connected: bool,
+    spectators: Option<Spectators<A>>,
+    /// Invalid messages and illegal moves since the client's last good move
+    strikes: usize,
+    /// A client coming back whose handshake has not arrived yet
+    returning: Option<PendingHandshake<A::Stream>>,
 }
 
-impl RemoteHostGame {
-    pub fn new(port: &String) -> std::io::Result<Self> {
-        let listener = TcpListener::bind(format!("0.0.0.0:{}", port))?;
+impl<A: Acceptor> RemoteHostGame<A> {
+    /**
+     * Wait for a client to connect through `acceptor` and start the game.
+     * Clients that lose their connection can come back through it later.
+     */
+    pub fn new(acceptor: A) -> std::io::Result<Self> {
+        return RemoteHostGame::with_rules(acceptor, Rules::default());
+    }
 
-        let (stream, addr) = listener.accept()?;
-        println!("Connected to {}", addr);
+    /**
+     * Like [RemoteHostGame::new], but the game is played by `rules`, which
+     * are also what the client is told is supported.
+     */
+    pub fn with_rules(mut acceptor: A, rules: Rules)
+        -> std::io::Result<Self> {
+        let mut stream = acceptor.accept()?;
 
-        let mut de = serde_json::Deserializer::from_reader(&stream);
-        let engine = LocalGame::new();
+        let mut reader = JsonReader::default();
+        let c2sh: ClientToServerHandshake
+            = reader.read(&mut stream, RESPONSE_TIMEOUT)?;
 
-        let c2sh = ClientToServerHandshake::deserialize(&mut de)?;
-        println!("Received: {:?}", c2sh);
+        return RemoteHostGame::start(acceptor, stream, reader, c2sh, rules);
+    }
 
+    /**
+     * Start the game with a client whose handshake `c2sh` arrived on
+     * `stream`.
+     */
+    fn start(acceptor: A, stream: A::Stream, reader: JsonReader,
+             c2sh: ClientToServerHandshake, rules: Rules)
+        -> std::io::Result<Self> {
+        let engine = LocalGame::with_rules(rules);
         let mut game = RemoteHostGame {
-            engine, stream,
+            acceptor, stream, reader, engine,
             server_color: c2sh.server_color,
-            last_client_move: Move {
-                start_x: 0,
-                start_y: 0,
-                end_x: 0,
-                end_y: 0,
-                promotion: Piece::None,
+            resigned: false,
+            message: None,
+            connected: true,
+            spectators: None,
+            strikes: 0,
+            returning: None,
+        };
+        game.send_handshake()?;
+
+        return Ok(game);
+    }
+
+    /**
+     * Mark the connection as lost, telling the user why.
+     */
+    fn disconnect(&mut self, reason: impl std::fmt::Display) {
+        self.connected = false;
+        self.message = Some(format!(
+            "Connection lost: {}. Waiting for the opponent to reconnect",
+            reason,
+        ));
+    }
+
+    fn send(&mut self, msg: &impl Serialize) -> std::io::Result<()> {
+        serde_json::to_writer(&mut self.stream, msg)?;
+        self.stream.flush()?;
+        return Ok(());
+    }
+
+    /**
+     * Let spectators watch the game, connecting through `acceptor`.
+     */
+    pub fn allow_spectators(&mut self, acceptor: A) {
+        self.spectators = Some(Spectators::new(acceptor));
+    }
+
+    fn handshake(&mut self) -> ServerToClientHandshake {
+        return ServerToClientHandshake {
+            features: proto_features(self.engine.get_rules()),
+            board: self.get_proto_board(),
+            moves: self.get_proto_moves(),
+            joever: self.proto_joever(),
+        };
+    }
+
+    fn send_handshake(&mut self) -> std::io::Result<()> {
+        let s2ch = self.handshake();
+        self.send(&s2ch)?;
+
+        return Ok(());
+    }
+
+    fn accept_spectators(&mut self) {
+        if let Some(mut spectators) = self.spectators.take() {
+            spectators.accept(|| self.handshake());
+            self.spectators = Some(spectators);
+        }
+    }
+
+    /**
+     * Show `msg` to the spectators, if there are any.
+     */
+    fn spectate(&mut self, msg: &impl Serialize) {
+        if let Some(spectators) = self.spectators.as_mut() {
+            spectators.broadcast(msg);
+        }
+    }
+
+    /**
+     * Accept a client coming back after the connection was lost, without
+     * waiting for it or its handshake. Returns [true] once the client is
+     * connected again.
+     */
+    fn accept_client(&mut self) -> std::io::Result<bool> {
+        let mut pending = match self.returning.take() {
+            Some(pending) => pending,
+            None => match self.acceptor.try_accept()? {
+                Some(stream) => PendingHandshake::new(stream),
+                None => return Ok(false),
+            },
+        };
+        let c2sh: ClientToServerHandshake = match pending.poll()? {
+            Some(c2sh) => c2sh,
+            None => {
+                self.returning = Some(pending);
+                return Ok(false);
             },
         };
 
-        let s2ch = ServerToClientHandshake {
-            features: vec![
-                Features::EnPassant,
-                Features::Castling,
-                Features::Promotion,
-            ],
-            board: game.get_proto_board(),
-            moves: game.get_proto_moves(),
-            joever: Joever::Ongoing,
+        let (stream, reader) = pending.into_parts();
+        if c2sh.server_color != self.server_color {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "the client asked for the wrong color",
+            ));
+        }
+
+        self.stream = stream;
+        self.reader = reader;
+        self.send_handshake()?;
+        self.connected = true;
+        self.strikes = 0;
+        self.message = Some(String::from("The opponent reconnected"));
+
+        return Ok(true);
+    }
+
+    /**
+     * Pick up a returning client if there is one, see
+     * [RemoteHostGame::accept_client]. Failed attempts are only logged.
+     */
+    fn check_for_client(&mut self) {
+        if let Err(e) = self.accept_client() {
+            eprintln!("Could not accept client: {}", e);
+        }
+    }
+
+    fn proto_joever(&mut self) -> Joever {
+        return proto_joever(self.get_state());
+    }
+
+    fn get_proto_board(&self) -> [[Piece; 8]; 8] {
+        return proto_board(&self.engine);
+    }
+
+    fn get_proto_moves(&self) -> Vec<Move> {
+        return proto_moves(&self.engine);
+    }
+
+    /**
+     * Answer the client with an error, without holding it against it.
+     */
+    fn send_error(&mut self, message: impl Into<String>)
+        -> std::io::Result<()> {
+        let s2c = ServerToClient::Error {
+            board: self.get_proto_board(),
+            moves: self.get_proto_moves(),
+            joever: self.proto_joever(),
+            message: message.into(),
         };
-        println!("Send S2CH");
-        serde_json::to_writer(&game.stream, &s2ch)?;
+        return self.send(&s2c);
+    }
 
-        /* the client is white and makes a move */
-        if game.server_color == Color::Black {
-            let _ = game.handle_client_move();
+    /**
+     * Tell the client its message was refused. Fails once the client sent
+     * [MAX_STRIKES] bad messages in a row.
+     */
+    fn reject(&mut self, message: impl Into<String>) -> std::io::Result<()> {
+        self.send_error(message)?;
+
+        self.strikes += 1;
+        if self.strikes >= MAX_STRIKES {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "the client sent too many invalid messages",
+            ));
         }
+        return Ok(());
+    }
 
-        return Ok(game);
+    /**
+     * Return [true] if it is the client's turn.
+     */
+    fn client_to_move(&self) -> bool {
+        let server_white = self.server_color == Color::White;
+        return self.engine.get_player() != server_white;
     }
 
-    fn handle_client_move(&mut self) -> std::io::Result<()> {
-        let mut de = serde_json::Deserializer::from_reader(&self.stream);
+    /**
+     * Return why the client may not make `mv`, if it may not.
+     */
+    fn check_move(&mut self, mv: &Move) -> Result<(), IllegalMove> {
+        if self.get_state() != ChessState::Ongoing {
+            return Err(IllegalMove::GameOver);
+        }
+        if !self.client_to_move() {
+            return Err(IllegalMove::NotYourTurn);
+        }
+        return check_proto_move(&self.engine, mv);
+    }
 
-        let mut done = false;
-        while !done {
-            let c2s = ClientToServer::deserialize(&mut de)?;
-            println!("Received: {:?}", c2s);
+    /**
+     * Handle what the client sent so far. Returns [true] once the client made
+     * its move.
+     */
+    fn poll_client(&mut self) -> std::io::Result<bool> {
+        loop {
+            let c2s = match self.reader.poll(&mut self.stream) {
+                Ok(Some(c2s)) => c2s,
+                Ok(None) => return Ok(false),
+                /* the reader drops what it could not read */
+                Err(e) if e.kind() == ErrorKind::InvalidData => {
+                    self.reject(format!("invalid message: {}", e))?;
+                    continue;
+                },
+                Err(e) => return Err(e),
+            };
 
             match c2s {
                 ClientToServer::Move(mv) => {
-                    let moves = self.get_proto_moves();
-                    if moves.contains(&mv) {
-                        /* move got accepted */
-                        self.last_client_move = mv;
-                        done = true;
-                    } else {
-                        let s2c = ServerToClient::Error {
-                            board: self.get_proto_board(),
-                            moves,
-                            joever: Joever::Ongoing,
-                            message: String::from("tu madre"),
-                        };
-                        println!("Send illegal move");
-                        serde_json::to_writer(&self.stream, &s2c)?;
+                    if let Err(reason) = self.check_move(&mv) {
+                        self.reject(reason.to_string())?;
+                        continue;
                     }
+
+                    /* move got accepted */
+                    self.strikes = 0;
+                    apply_proto_move(&mut self.engine, &mv);
+                    self.update_client(&mv)?;
+                    return Ok(true);
                 },
-                ClientToServer::Resign => todo!(),
-                ClientToServer::Draw => todo!(),
+                ClientToServer::Resign
+                    if self.get_state() != ChessState::Ongoing
+                    => self.reject(IllegalMove::GameOver.to_string())?,
+                ClientToServer::Resign => {
+                    self.resigned = true;
+                    self.message = Some(String::from("The opponent resigned"));
+                    let s2c = ServerToClient::Resigned {
+                        board: self.get_proto_board(),
+                        joever: self.proto_joever(),
+                    };
+                    self.spectate(&s2c);
+                    self.send(&s2c)?;
+                    return Ok(true);
+                },
+                /* a valid message, just not one we act on */
+                ClientToServer::Draw
+                    => self.send_error("Draw offers are not supported")?,
             }
         }
-
-        let mv = self.last_client_move;
-        self.engine.apply_move(&self.move_to_chess_move(&mv));
-        println!("Send legal move");
-        self.update_client(&mv)?;
-
-        return Ok(());
     }
 
     fn update_client(&mut self, mv: &Move) -> std::io::Result<()> {
-        use ChessState::*;
-
         let s2c = ServerToClient::State {
             board: self.get_proto_board(),
             moves: self.get_proto_moves(),
-            joever: match self.get_state() {
-                Ongoing => Joever::Ongoing,
-                JoeverIndeterminate => Joever::Indeterminate,
-                JoeverDraw => Joever::Draw,
-                JoeverWhite => Joever::White,
-                JoeverBlack => Joever::Black,
-            },
+            joever: self.proto_joever(),
             move_made: *mv,
         };
-        serde_json::to_writer(&self.stream, &s2c)?;
+        self.spectate(&s2c);
+
+        /* a reconnecting client gets the new board in the handshake */
+        if self.connected {
+            self.send(&s2c)?;
+        }
 
         return Ok(());
     }
+}
 
-    fn chess_move_to_move(&self, mv: &ChessMove) -> Move {
-        return Move {
-            start_x: mv.from.0 as usize,
-            start_y: mv.from.1 as usize,
-            end_x: mv.to.0 as usize,
-            end_y: mv.to.1 as usize,
-            promotion: if mv.promotion {
-                if self.get_player() {
-                    Piece::WhiteQueen
-                } else {
-                    Piece::BlackQueen
-                }
-            } else {
-                Piece::None
+/**
+ * A host waiting for its client without blocking, for callers that cannot
+ * wait in [RemoteHostGame::new], e.g. while drawing frames.
+ */
+pub struct WaitingHost<A: Acceptor> {
+    /// Handed to the game once it starts
+    acceptor: Option<A>,
+    rules: Rules,
+    /// A client whose handshake has not arrived yet
+    pending: Option<PendingHandshake<A::Stream>>,
+}
+
+impl<A: Acceptor> WaitingHost<A> {
+    /**
+     * Wait for a client through `acceptor`, see [RemoteHostGame::with_rules].
+     */
+    pub fn new(acceptor: A, rules: Rules) -> Self {
+        return WaitingHost { acceptor: Some(acceptor), rules, pending: None };
+    }
+
+    /**
+     * Return the game once a client connected and sent its handshake.
+     * Connections that fail before that are dropped and the wait goes on.
+     */
+    pub fn poll(&mut self) -> std::io::Result<Option<RemoteHostGame<A>>> {
+        let acceptor = match self.acceptor.as_mut() {
+            Some(acceptor) => acceptor,
+            None => return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "the game already started",
+            )),
+        };
+        let mut pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => match acceptor.try_accept()? {
+                Some(stream) => PendingHandshake::new(stream),
+                None => return Ok(None),
+            },
+        };
+        let c2sh: ClientToServerHandshake = match pending.poll() {
+            Ok(Some(c2sh)) => c2sh,
+            Ok(None) => {
+                self.pending = Some(pending);
+                return Ok(None);
             },
+            /* not a client that can play, the next one may be */
+            Err(_) => return Ok(None),
         };
+
+        let (stream, reader) = pending.into_parts();
+        let acceptor = self.acceptor.take().unwrap();
+        return RemoteHostGame::start(acceptor, stream, reader, c2sh,
+                                     self.rules).map(Some);
     }
+}
 
-    fn move_to_chess_move(&self, mv: &Move) -> ChessMove {
-        let from = (mv.start_x as i32, mv.start_y as i32);
-        let to = (mv.end_x as i32, mv.end_y as i32);
-        return ChessMove {
-            from,
-            to,
-            /* NOTE: this is only used for client visualization, where this
-             * function will never be called */
-            capture: false,
-            promotion: mv.promotion != Piece::None,
-        };
+impl<A: Acceptor> ChessGame for RemoteHostGame<A> {
+    fn get_moves(&mut self, loc: &ChessLoc) -> HashMap<ChessLoc, ChessMove> {
+        return self.engine.get_moves(loc);
     }
 
-    fn get_proto_board(&self) -> [[chess_network_protocol::Piece; 8]; 8] {
-        let board = self.engine.get_board();
-        let mut out = [[Piece::None; 8]; 8];
-        for y in 0..8 {
-            for x in 0..8 {
-                if let Square::Occupied(piece) = board[8*y + x] {
-                    out[y][x] = local_piece_to_proto(piece);
-                }
+    fn apply_move(&mut self, mv: &ChessMove) -> bool {
+        /* turn down whatever the client sent out of turn first */
+        if self.connected {
+            if let Err(e) = self.poll_client() {
+                self.disconnect(e);
             }
         }
+        if self.resigned {
+            return false;
+        }
 
-        return out;
+        let proto_mv = chess_move_to_proto(mv, self.get_player());
+        let ret = self.engine.apply_move(mv);
+        if let Err(e) = self.update_client(&proto_mv) {
+            self.disconnect(e);
+        }
+        /* a returning client gets the board with this move made */
+        if !self.connected {
+            self.check_for_client();
+        }
+        self.accept_spectators();
+
+        return ret;
     }
 
-    fn get_proto_moves(&self) -> Vec<Move> {
-        let moves = self.engine.get_all_moves();
-        let mut out: Vec<Move> = Vec::new();
-        for mv in moves.iter() {
-            out.push(self.chess_move_to_move(&mv));
+    fn wait_move(&mut self) -> bool {
+        self.accept_spectators();
+
+        if !self.connected {
+            self.check_for_client();
+            return false;
         }
-        return out;
-    }
-}
 
-impl ChessGame for RemoteHostGame {
-    fn get_moves(&mut self, loc: &ChessLoc) -> HashMap<ChessLoc, ChessMove> {
-        return self.engine.get_moves(loc);
+        return match self.poll_client() {
+            Ok(moved) => moved,
+            Err(e) => {
+                self.disconnect(e);
+                false
+            },
+        };
     }
 
-    fn apply_move(&mut self, mv: &ChessMove) -> bool {
-        let ret = self.engine.apply_move(mv);
+    fn opponent_starts(&self) -> bool {
+        /* the client is white and makes the first move */
+        return self.server_color == Color::Black;
+    }
 
-        println!("Send server move");
-        let _ = self.update_client(&self.chess_move_to_move(mv));
+    fn rules(&self) -> Vec<String> {
+        return self.engine.rules();
+    }
 
-        return ret;
+    fn take_message(&mut self) -> Option<String> {
+        return self.message.take();
     }
 
-    fn wait_move(&mut self) -> bool {
-        let _ = self.handle_client_move();
-        return true;
+    fn is_connected(&self) -> bool {
+        return self.connected;
     }
 
     fn get_piece(&mut self, loc: &ChessLoc) -> (bool, String) {
@@ -222,6 +445,13 @@ impl ChessGame for RemoteHostGame {
     }
 
     fn get_state(&mut self) -> ChessState {
+        if self.resigned {
+            return if self.server_color == Color::White {
+                ChessState::JoeverWhite
+            } else {
+                ChessState::JoeverBlack
+            };
+        }
         return self.engine.get_state();
     }
 }
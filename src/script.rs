@@ -0,0 +1,492 @@
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chess_network_protocol::*;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::chess_engine::*;
+use crate::connection::JsonReader;
+use crate::local_engine::{LocalGame, Rules};
+use crate::protocol::*;
+
+/*
+ * Scripts drive one side of a connection line by line, to test how another
+ * implementation copes with edge cases. Empty lines and lines starting with
+ * `#` are skipped. Both sides understand:
+ *
+ *     send <JSON>          send a message as written
+ *     send-raw <TEXT>      send TEXT as is, e.g. broken JSON
+ *     expect <WHAT>        wait for a message, see [Expectation]
+ *     expect-nothing <MS>  fail if a message arrives within MS milliseconds
+ *     expect-closed        wait for the peer to close the connection
+ *     sleep <MS>           wait MS milliseconds
+ *     timeout <SECONDS>    how long `expect` waits from now on (default 30)
+ *     disconnect           close the connection and end the script
+ *
+ * A client also understands `handshake white|black` (the color the server
+ * plays), `move <MOVE>`, `resign` and `draw`. A server keeps a game of its
+ * own and understands `handshake [FEATURES]`, `state [MOVE]` (play MOVE and
+ * send the new state, even if MOVE is illegal, or confirm the client's last
+ * legal move without MOVE), `error <MESSAGE>`, `resigned` and `draw`. Moves
+ * are written like `e2e4` or `e7e8q`.
+ *
+ * The server's handshake lists the features given, out of `castling`,
+ * `en-passant` and `promotion`, or `none` of them. Without any it lists all
+ * three. The server's game is played by the rules the features allow.
+ */
+
+/// How long `expect` waits unless the script says otherwise
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Role {
+    Server,
+    Client,
+}
+
+/**
+ * What an `expect` line accepts.
+ */
+#[derive(Clone, Debug)]
+pub enum Expectation {
+    /// Any message
+    Any,
+    /// A message of the given kind, e.g. `Move` or `State`
+    Kind(String),
+    /// A message containing everything in the value, fields it leaves out
+    /// can be anything
+    Matching(Value),
+}
+
+#[derive(Clone, Debug)]
+pub enum Step {
+    Send(Value),
+    SendRaw(String),
+    Expect(Expectation),
+    ExpectNothing(Duration),
+    ExpectClosed,
+    Sleep(Duration),
+    Timeout(Duration),
+    Disconnect,
+    /// The client's handshake with the color the server plays
+    Handshake(Color),
+    /// The server's handshake with its board and the features it lists
+    ServerHandshake(Vec<Features>),
+    Move(String),
+    Resign,
+    Draw,
+    /// [None] for the client's last move
+    State(Option<String>),
+    Error(String),
+    Resigned,
+}
+
+pub struct Line {
+    /// Counting from 1
+    pub number: usize,
+    pub step: Step,
+}
+
+fn parse_millis(arg: &str) -> Result<Duration, String> {
+    return arg.parse().map(Duration::from_millis)
+        .map_err(|_| format!("Invalid number of milliseconds: {}", arg));
+}
+
+/**
+ * Parse the features of a server's handshake, see the top of this file.
+ */
+fn parse_features(arg: &str) -> Result<Vec<Features>, String> {
+    if arg.is_empty() {
+        return Ok(vec![
+            Features::EnPassant,
+            Features::Castling,
+            Features::Promotion,
+        ]);
+    }
+    if arg == "none" {
+        return Ok(Vec::new());
+    }
+
+    let mut features = Vec::new();
+    for name in arg.split_whitespace() {
+        features.push(match name {
+            "castling" => Features::Castling,
+            "en-passant" => Features::EnPassant,
+            "promotion" => Features::Promotion,
+            _ => return Err(format!("Unknown feature: {}", name)),
+        });
+    }
+    return Ok(features);
+}
+
+fn parse_step(role: Role, command: &str, arg: &str) -> Result<Step, String> {
+    let client = role == Role::Client;
+    return Ok(match command {
+        "send" => Step::Send(serde_json::from_str(arg)
+            .map_err(|e| format!("Invalid JSON: {}", e))?),
+        "send-raw" => Step::SendRaw(String::from(arg)),
+        "expect" => Step::Expect(match arg {
+            "" => Expectation::Any,
+            _ if arg.starts_with(['{', '[', '"']) => Expectation::Matching(
+                serde_json::from_str(arg)
+                    .map_err(|e| format!("Invalid JSON: {}", e))?,
+            ),
+            _ => Expectation::Kind(String::from(arg)),
+        }),
+        "expect-nothing" => Step::ExpectNothing(parse_millis(arg)?),
+        "expect-closed" => Step::ExpectClosed,
+        "sleep" => Step::Sleep(parse_millis(arg)?),
+        "timeout" => Step::Timeout(Duration::from_secs(arg.parse()
+            .map_err(|_| format!("Invalid number of seconds: {}", arg))?)),
+        "disconnect" => Step::Disconnect,
+        "handshake" if client => Step::Handshake(match arg {
+            "white" => Color::White,
+            "black" => Color::Black,
+            _ => return Err(format!("Invalid color: {}", arg)),
+        }),
+        "handshake" => Step::ServerHandshake(parse_features(arg)?),
+        "move" if client => {
+            parse_move(arg, true)?;
+            Step::Move(String::from(arg))
+        },
+        "resign" if client => Step::Resign,
+        "draw" => Step::Draw,
+        "state" if !client && arg.is_empty() => Step::State(None),
+        "state" if !client => {
+            parse_move(arg, true)?;
+            Step::State(Some(String::from(arg)))
+        },
+        "error" if !client => Step::Error(String::from(arg)),
+        "resigned" if !client => Step::Resigned,
+        _ => return Err(format!("Unknown command for a {:?}: {}",
+                                role, command)),
+    });
+}
+
+/**
+ * Parse a script for `role`, see the top of this file.
+ */
+pub fn parse(role: Role, text: &str) -> Result<Vec<Line>, String> {
+    let mut lines = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let step = parse_step(role, command, arg.trim())
+            .map_err(|e| format!("line {}: {}", i + 1, e))?;
+        lines.push(Line { number: i + 1, step });
+    }
+
+    return Ok(lines);
+}
+
+/**
+ * Parse a move like `e2e4`, or `e7e8q` for a promotion by `white` (or
+ * black).
+ */
+pub fn parse_move(text: &str, white: bool) -> Result<Move, String> {
+    let invalid = || format!("Invalid move: {}", text);
+    let bytes = text.as_bytes();
+    if !(4..=5).contains(&bytes.len()) {
+        return Err(invalid());
+    }
+
+    let coord = |i: usize, base: u8| -> Result<usize, String> {
+        let c = bytes[i].wrapping_sub(base);
+        return if c < 8 { Ok(c as usize) } else { Err(invalid()) };
+    };
+    let promotion = match (bytes.get(4), white) {
+        (None, _) => Piece::None,
+        (Some(b'q'), true) => Piece::WhiteQueen,
+        (Some(b'r'), true) => Piece::WhiteRook,
+        (Some(b'b'), true) => Piece::WhiteBishop,
+        (Some(b'n'), true) => Piece::WhiteKnight,
+        (Some(b'q'), false) => Piece::BlackQueen,
+        (Some(b'r'), false) => Piece::BlackRook,
+        (Some(b'b'), false) => Piece::BlackBishop,
+        (Some(b'n'), false) => Piece::BlackKnight,
+        _ => return Err(invalid()),
+    };
+
+    return Ok(Move {
+        start_x: coord(0, b'a')?,
+        start_y: coord(1, b'1')?,
+        end_x: coord(2, b'a')?,
+        end_y: coord(3, b'1')?,
+        promotion,
+    });
+}
+
+/**
+ * Return [true] if `message` has everything in `pattern`.
+ */
+fn matches_pattern(message: &Value, pattern: &Value) -> bool {
+    return match (message, pattern) {
+        (Value::Object(message), Value::Object(pattern)) => {
+            pattern.iter().all(|(key, pattern)| {
+                message.get(key)
+                    .is_some_and(|value| matches_pattern(value, pattern))
+            })
+        },
+        _ => message == pattern,
+    };
+}
+
+/**
+ * The kind of a message: its variant for enums, which serde writes as a
+ * string or as an object with a single key.
+ */
+fn message_kind(message: &Value) -> Option<&str> {
+    return match message {
+        Value::String(kind) => Some(kind),
+        Value::Object(fields) if fields.len() == 1 => {
+            fields.keys().next().map(|kind| kind.as_str())
+        },
+        _ => None,
+    };
+}
+
+impl Expectation {
+    fn accepts(&self, message: &Value) -> bool {
+        return match self {
+            Expectation::Any => true,
+            Expectation::Kind(kind) => message_kind(message) == Some(kind),
+            Expectation::Matching(pattern) => matches_pattern(message, pattern),
+        };
+    }
+}
+
+/**
+ * Runs a script over a connection.
+ */
+pub struct Runner<S: Read + Write> {
+    stream: S,
+    reader: JsonReader,
+    role: Role,
+    /// The server's game, see [Step::State]
+    engine: LocalGame,
+    /// Color the client plays, for its promotions
+    client_white: bool,
+    /// The client's last legal move, played in `engine` by [Step::State]
+    client_move: Option<Move>,
+    timeout: Duration,
+    failures: usize,
+}
+
+impl<S: Read + Write> Runner<S> {
+    pub fn new(stream: S, role: Role) -> Self {
+        return Runner {
+            stream,
+            reader: JsonReader::default(),
+            role,
+            engine: LocalGame::new(),
+            client_white: true,
+            client_move: None,
+            timeout: DEFAULT_TIMEOUT,
+            failures: 0,
+        };
+    }
+
+    fn send(&mut self, msg: &impl Serialize) -> io::Result<()> {
+        let text = serde_json::to_string(msg)?;
+        println!("-> {}", text);
+        self.stream.write_all(text.as_bytes())?;
+        self.stream.flush()?;
+        return Ok(());
+    }
+
+    fn fail(&mut self, line: &Line, problem: String) {
+        self.failures += 1;
+        println!("FAIL line {}: {}", line.number, problem);
+    }
+
+    /**
+     * Wait up to `timeout` for a message. [None] if none arrived in time.
+     */
+    fn receive(&mut self, timeout: Duration) -> io::Result<Option<Value>> {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if let Some(message) = self.reader.poll(&mut self.stream)? {
+                println!("<- {}", message);
+                self.follow(&message);
+                return Ok(Some(message));
+            }
+        }
+        return Ok(None);
+    }
+
+    /**
+     * Remember the client's last legal move for [Step::State].
+     */
+    fn follow(&mut self, message: &Value) {
+        if self.role != Role::Server {
+            return;
+        }
+        let mv = match serde_json::from_value(message.clone()) {
+            Ok(ClientToServer::Move(mv)) => mv,
+            _ => return,
+        };
+
+        if proto_moves(&self.engine).contains(&mv) {
+            self.client_move = Some(mv);
+        }
+    }
+
+    fn server_state(&mut self, mv: Move) -> ServerToClient {
+        return ServerToClient::State {
+            board: proto_board(&self.engine),
+            moves: proto_moves(&self.engine),
+            joever: proto_joever(self.engine.get_state()),
+            move_made: mv,
+        };
+    }
+
+    /**
+     * Run one line. Returns [false] once the script has to end.
+     */
+    fn step(&mut self, line: &Line) -> io::Result<bool> {
+        match &line.step {
+            Step::Send(value) => self.send(value)?,
+            Step::SendRaw(text) => {
+                println!("-> (raw) {}", text);
+                self.stream.write_all(text.as_bytes())?;
+                self.stream.flush()?;
+            },
+            Step::Expect(expectation) => match self.receive(self.timeout)? {
+                Some(message) if expectation.accepts(&message) => (),
+                Some(message) => self.fail(line, format!(
+                    "expected {:?}, got {}", expectation, message,
+                )),
+                None => self.fail(line, format!(
+                    "expected {:?}, got nothing", expectation,
+                )),
+            },
+            Step::ExpectNothing(duration) => {
+                if let Some(message) = self.receive(*duration)? {
+                    self.fail(line, format!("expected nothing, got {}",
+                                            message));
+                }
+            },
+            Step::ExpectClosed => {
+                let deadline = Instant::now() + self.timeout;
+                loop {
+                    match self.reader.poll::<Value, _>(&mut self.stream) {
+                        Ok(Some(message)) => println!("<- {}", message),
+                        Ok(None) if Instant::now() < deadline => (),
+                        Ok(None) => {
+                            self.fail(line, String::from(
+                                "expected the peer to close the connection",
+                            ));
+                            break;
+                        },
+                        Err(_) => {
+                            println!("<- (closed)");
+                            return Ok(false);
+                        },
+                    }
+                }
+            },
+            Step::Sleep(duration) => thread::sleep(*duration),
+            Step::Timeout(timeout) => self.timeout = *timeout,
+            Step::Disconnect => {
+                println!("-> (disconnect)");
+                return Ok(false);
+            },
+            Step::Handshake(server_color) => {
+                self.client_white = *server_color == Color::Black;
+                let c2sh = ClientToServerHandshake {
+                    server_color: *server_color,
+                };
+                self.send(&c2sh)?;
+            },
+            Step::ServerHandshake(features) => {
+                /* the game starts over, played by what the features allow */
+                self.engine = LocalGame::with_rules(Rules {
+                    castling: features.iter()
+                        .any(|f| matches!(f, Features::Castling)),
+                    en_passant: features.iter()
+                        .any(|f| matches!(f, Features::EnPassant)),
+                });
+
+                let s2ch = ServerToClientHandshake {
+                    features: features.clone(),
+                    board: proto_board(&self.engine),
+                    moves: proto_moves(&self.engine),
+                    joever: proto_joever(self.engine.get_state()),
+                };
+                self.send(&s2ch)?;
+            },
+            Step::Move(mv) => {
+                let mv = parse_move(mv, self.client_white)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput,
+                                                e))?;
+                self.send(&ClientToServer::Move(mv))?;
+            },
+            Step::Resign => self.send(&ClientToServer::Resign)?,
+            Step::Draw if self.role == Role::Client
+                => self.send(&ClientToServer::Draw)?,
+            Step::Draw => {
+                let s2c = ServerToClient::Draw {
+                    board: proto_board(&self.engine),
+                    moves: proto_moves(&self.engine),
+                };
+                self.send(&s2c)?;
+            },
+            Step::State(Some(mv)) => {
+                let white = self.engine.get_player();
+                let mv = parse_move(mv, white)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput,
+                                                e))?;
+                /* illegal moves are reported all the same */
+                apply_proto_move(&mut self.engine, &mv);
+                let s2c = self.server_state(mv);
+                self.send(&s2c)?;
+            },
+            Step::State(None) => match self.client_move.take() {
+                Some(mv) => {
+                    apply_proto_move(&mut self.engine, &mv);
+                    let s2c = self.server_state(mv);
+                    self.send(&s2c)?;
+                },
+                None => self.fail(line, String::from(
+                    "the client has not made a legal move to confirm",
+                )),
+            },
+            Step::Error(message) => {
+                let s2c = ServerToClient::Error {
+                    board: proto_board(&self.engine),
+                    moves: proto_moves(&self.engine),
+                    joever: proto_joever(self.engine.get_state()),
+                    message: message.clone(),
+                };
+                self.send(&s2c)?;
+            },
+            Step::Resigned => {
+                let s2c = ServerToClient::Resigned {
+                    board: proto_board(&self.engine),
+                    joever: proto_joever(self.engine.get_state()),
+                };
+                self.send(&s2c)?;
+            },
+        }
+
+        return Ok(true);
+    }
+
+    /**
+     * Run the whole script. Returns the number of failed expectations.
+     */
+    pub fn run(&mut self, lines: &[Line]) -> io::Result<usize> {
+        for line in lines {
+            if !self.step(line)? {
+                break;
+            }
+        }
+        return Ok(self.failures);
+    }
+}
@@ -0,0 +1,100 @@
+use std::{fs, path::Path};
+use serde::{Serialize, Deserialize};
+
+use sieric_chess_gui::remote_engine::Verification;
+
+const SETTINGS_FILE: &str = "settings.json";
+
+/// How long a move animation takes by default, in seconds
+pub const DEFAULT_ANIM_DURATION: f32 = 0.2;
+
+/// How many recently joined addresses are remembered
+const MAX_RECENT_ADDRESSES: usize = 5;
+
+/**
+ * User preferences that are remembered between runs. Missing fields fall back
+ * to their defaults, so older settings files keep working.
+ */
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub music_volume: f32,
+    pub effects_volume: f32,
+    pub music_muted: bool,
+    pub effects_muted: bool,
+    /// Music file or directory, [None] for the bundled track
+    pub music_path: Option<String>,
+    /// Name of the board theme, see [crate::theme::THEMES]
+    pub theme: String,
+    /// Name of the piece set, see [crate::theme::PieceSet]
+    pub piece_set: String,
+    /// Turn the board around so the side to move is at the bottom
+    pub flip_mode: bool,
+    pub anim_duration: f32,
+    /// Port filled in when hosting or joining
+    pub default_port: Option<u16>,
+    /// Most recently joined addresses, newest first
+    pub recent_addresses: Vec<String>,
+    pub player_name: String,
+    /// Whether to check what servers send when joining a game
+    pub verification: Verification,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        return Settings {
+            music_volume: 1.,
+            effects_volume: 1.,
+            /* the music used to start paused */
+            music_muted: true,
+            effects_muted: false,
+            music_path: None,
+            theme: String::from("classic"),
+            piece_set: String::from(crate::theme::LETTERS),
+            flip_mode: false,
+            anim_duration: DEFAULT_ANIM_DURATION,
+            default_port: None,
+            recent_addresses: Vec::new(),
+            player_name: String::new(),
+            verification: Verification::Off,
+        };
+    }
+}
+
+impl Settings {
+    /**
+     * Read the settings from `dir`. Falls back to the defaults if there is no
+     * settings file or it cannot be parsed.
+     */
+    pub fn load(dir: &Path) -> Self {
+        let path = dir.join(SETTINGS_FILE);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => return Settings::default(),
+        };
+
+        return match serde_json::from_str(&text) {
+            Ok(settings) => settings,
+            Err(e) => {
+                eprintln!("Ignoring invalid {}: {}", path.display(), e);
+                Settings::default()
+            },
+        };
+    }
+
+    /**
+     * Put `addr` at the top of the recently joined addresses.
+     */
+    pub fn add_recent_address(&mut self, addr: String) {
+        self.recent_addresses.retain(|a| *a != addr);
+        self.recent_addresses.insert(0, addr);
+        self.recent_addresses.truncate(MAX_RECENT_ADDRESSES);
+    }
+
+    pub fn save(&self, dir: &Path) -> std::io::Result<()> {
+        fs::create_dir_all(dir)?;
+        let file = fs::File::create(dir.join(SETTINGS_FILE))?;
+        serde_json::to_writer_pretty(file, self)?;
+        return Ok(());
+    }
+}
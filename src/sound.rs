@@ -0,0 +1,288 @@
+use std::{collections::HashMap, fs, path::{Path, PathBuf}};
+
+use ggez::{Context, GameResult};
+use ggez::audio::{self, SoundSource};
+
+use crate::animation::BoardSnapshot;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SoundEvent {
+    Move,
+    Capture,
+    Castle,
+    Promotion,
+    Check,
+    GameEnd,
+}
+
+impl SoundEvent {
+    const ALL: [SoundEvent; 6] = [
+        SoundEvent::Move,
+        SoundEvent::Capture,
+        SoundEvent::Castle,
+        SoundEvent::Promotion,
+        SoundEvent::Check,
+        SoundEvent::GameEnd,
+    ];
+
+    fn path(&self) -> &'static str {
+        use SoundEvent::*;
+
+        return match self {
+            Move => "/sounds/move.ogg",
+            Capture => "/sounds/capture.ogg",
+            Castle => "/sounds/castle.ogg",
+            Promotion => "/sounds/promotion.ogg",
+            Check => "/sounds/check.ogg",
+            GameEnd => "/sounds/game_end.ogg",
+        };
+    }
+}
+
+/**
+ * The sound effects played on game events. Effects whose file is missing from
+ * the resource path are left out.
+ */
+pub struct Sounds {
+    sources: HashMap<SoundEvent, audio::Source>,
+    pub muted: bool,
+}
+
+impl Sounds {
+    pub fn new(ctx: &mut Context, volume: f32, muted: bool) -> Self {
+        let mut sources = HashMap::new();
+        let mut missing = Vec::new();
+        for event in SoundEvent::ALL {
+            match audio::Source::new(ctx, event.path()) {
+                Ok(mut source) => {
+                    source.set_volume(volume);
+                    sources.insert(event, source);
+                },
+                Err(_) => missing.push(event.path()),
+            }
+        }
+
+        if !missing.is_empty() {
+            eprintln!("Warning: missing sound effects: {}", missing.join(", "));
+        }
+
+        return Sounds { sources, muted };
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        for source in self.sources.values_mut() {
+            source.set_volume(volume);
+        }
+    }
+
+    pub fn play(&mut self, ctx: &mut Context, event: SoundEvent) {
+        if self.muted {
+            return;
+        }
+
+        if let Some(source) = self.sources.get_mut(&event) {
+            let _ = source.play_detached(ctx);
+        }
+    }
+}
+
+/// Played when the user has not picked any music of their own
+const DEFAULT_MUSIC: &str = "/copyright_infringement.flac";
+
+/// File extensions picked up when the music path is a directory
+const MUSIC_EXTENSIONS: [&str; 4] = ["flac", "ogg", "wav", "mp3"];
+
+fn is_music_file(path: &Path) -> bool {
+    return match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => MUSIC_EXTENSIONS.contains(&ext.to_lowercase().as_str()),
+        None => false,
+    };
+}
+
+/**
+ * Background music. Either the default track from the resource path, a single
+ * file or every audio file in a directory, played in a loop. If nothing can
+ * be loaded the game simply runs without music.
+ */
+pub struct Music {
+    /// Whether `tracks` are file system paths rather than resource paths
+    external: bool,
+    tracks: Vec<PathBuf>,
+    current: usize,
+    source: Option<audio::Source>,
+    volume: f32,
+    muted: bool,
+}
+
+impl Music {
+    /**
+     * `path` is a file or directory anywhere on the file system. With [None]
+     * the default track from the resource path is used.
+     */
+    pub fn new(
+        ctx: &mut Context,
+        path: Option<&str>,
+        volume: f32,
+        muted: bool,
+    ) -> Self {
+        let tracks = match path {
+            Some(path) => Music::find_tracks(Path::new(path)),
+            None => vec![PathBuf::from(DEFAULT_MUSIC)],
+        };
+        if tracks.is_empty() {
+            eprintln!("Warning: no music found in {}", path.unwrap_or(""));
+        }
+
+        let mut music = Music {
+            external: path.is_some(),
+            tracks,
+            current: 0,
+            source: None,
+            volume,
+            muted,
+        };
+        music.load(ctx);
+
+        return music;
+    }
+
+    fn find_tracks(path: &Path) -> Vec<PathBuf> {
+        if !path.is_dir() {
+            return vec![path.to_path_buf()];
+        }
+
+        let mut tracks: Vec<PathBuf> = match fs::read_dir(path) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| is_music_file(path))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        tracks.sort();
+
+        return tracks;
+    }
+
+    fn open(ctx: &mut Context, path: &Path, external: bool)
+        -> GameResult<audio::Source> {
+        if !external {
+            return audio::Source::new(ctx, path);
+        }
+
+        let bytes = fs::read(path)?;
+        let data = audio::SoundData::from_bytes(&bytes);
+        return audio::Source::from_data(ctx, data);
+    }
+
+    /**
+     * Load the current track, skipping over tracks that fail to load.
+     */
+    fn load(&mut self, ctx: &mut Context) {
+        self.source = None;
+
+        for _ in 0..self.tracks.len() {
+            let path = self.tracks[self.current].clone();
+            match Music::open(ctx, &path, self.external) {
+                Ok(mut source) => {
+                    source.set_volume(self.volume);
+                    self.source = Some(source);
+                    return;
+                },
+                Err(e) => {
+                    eprintln!("Warning: could not load music {}: {}",
+                              path.display(), e);
+                    self.tracks.remove(self.current);
+                    if self.tracks.is_empty() {
+                        return;
+                    }
+                    self.current %= self.tracks.len();
+                },
+            }
+        }
+    }
+
+    /**
+     * Keep the music going. Moves on to the next track once the current one
+     * has finished.
+     */
+    pub fn update(&mut self, ctx: &mut Context) {
+        let stopped = match &self.source {
+            Some(source) => source.stopped(),
+            None => return,
+        };
+        if !stopped {
+            return;
+        }
+
+        if self.tracks.len() > 1 {
+            self.current = (self.current + 1) % self.tracks.len();
+            self.load(ctx);
+        }
+
+        if let Some(source) = &self.source {
+            let _ = source.play_later();
+            if self.muted {
+                source.pause();
+            }
+        }
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+        if let Some(source) = &mut self.source {
+            source.set_volume(volume);
+        }
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        if let Some(source) = &self.source {
+            if muted {
+                source.pause();
+            } else {
+                source.resume();
+            }
+        }
+    }
+}
+
+fn find_king(board: &BoardSnapshot, white: bool) -> Option<usize> {
+    return board.iter().position(|(w, name)| *w == white && name == "K");
+}
+
+fn count_officers(board: &BoardSnapshot, white: bool) -> usize {
+    return board.iter().filter(|(w, name)| {
+        *w == white && !name.trim().is_empty() && name != "P" && name != "K"
+    }).count();
+}
+
+/**
+ * Figure out what kind of move turned `before` into `after`. Check and the end
+ * of the game cannot be seen from the board alone, the caller has to ask the
+ * engine about those.
+ */
+pub fn classify_move(before: &BoardSnapshot, after: &BoardSnapshot)
+    -> SoundEvent {
+    for white in [true, false] {
+        if let (Some(from), Some(to))
+            = (find_king(before, white), find_king(after, white)) {
+            if (from as i32 % 8 - to as i32 % 8).abs() == 2 {
+                return SoundEvent::Castle;
+            }
+        }
+
+        if count_officers(after, white) > count_officers(before, white) {
+            return SoundEvent::Promotion;
+        }
+    }
+
+    let count = |board: &BoardSnapshot| {
+        board.iter().filter(|(_, name)| !name.trim().is_empty()).count()
+    };
+    if count(after) < count(before) {
+        return SoundEvent::Capture;
+    }
+
+    return SoundEvent::Move;
+}
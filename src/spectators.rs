@@ -0,0 +1,121 @@
+use std::io::{self, Write};
+
+use chess_network_protocol::*;
+use serde::Serialize;
+
+use crate::connection::PendingHandshake;
+use crate::transport::{Acceptor, Nonblocking};
+
+/**
+ * Read-only connections watching a hosted game. They connect through an
+ * acceptor of their own, so they can never be mistaken for a player, do the
+ * usual handshake and then receive every message sent to the players.
+ *
+ * Spectators never make the game wait: their streams do not block, and those
+ * that do not keep up with the game are dropped.
+ */
+pub struct Spectators<A: Acceptor> {
+    acceptor: A,
+    /// Spectators whose handshake has not arrived yet
+    pending: Vec<PendingHandshake<A::Stream>>,
+    streams: Vec<A::Stream>,
+}
+
+impl<A: Acceptor> Spectators<A> {
+    pub fn new(acceptor: A) -> Self {
+        return Spectators {
+            acceptor,
+            pending: Vec::new(),
+            streams: Vec::new(),
+        };
+    }
+
+    pub fn len(&self) -> usize {
+        return self.streams.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.streams.is_empty();
+    }
+
+    pub fn describe(&self) -> String {
+        return self.acceptor.describe();
+    }
+
+    /**
+     * Let in everyone waiting to watch whose handshake has arrived.
+     * `handshake` is only called if there is someone, since building it is
+     * not free.
+     */
+    pub fn accept(&mut self,
+                  mut handshake: impl FnMut() -> ServerToClientHandshake) {
+        loop {
+            match self.acceptor.try_accept() {
+                Ok(Some(stream)) => match stream.set_nonblocking(true) {
+                    Ok(()) => self.pending.push(PendingHandshake::new(stream)),
+                    Err(e) => eprintln!("Could not accept spectator: {}", e),
+                },
+                Ok(None) => break,
+                Err(e) => {
+                    eprintln!("Could not accept spectator: {}", e);
+                    break;
+                },
+            }
+        }
+
+        let mut i = 0;
+        while i < self.pending.len() {
+            /* the requested color means nothing to a spectator */
+            let greeted = match self.pending[i].poll() {
+                Ok(Some(ClientToServerHandshake { .. })) => {
+                    let pending = self.pending.swap_remove(i);
+                    self.greet(pending, &handshake())
+                },
+                Ok(None) => {
+                    i += 1;
+                    continue;
+                },
+                Err(e) => {
+                    self.pending.swap_remove(i);
+                    Err(e)
+                },
+            };
+
+            if let Err(e) = greeted {
+                eprintln!("Spectator failed the handshake: {}", e);
+            }
+        }
+    }
+
+    fn greet(&mut self, pending: PendingHandshake<A::Stream>,
+             s2ch: &ServerToClientHandshake) -> io::Result<()> {
+        let (mut stream, _) = pending.into_parts();
+        serde_json::to_writer(&mut stream, s2ch)?;
+        stream.flush()?;
+
+        self.streams.push(stream);
+        return Ok(());
+    }
+
+    /**
+     * Send `msg` to every spectator, dropping those that are gone or whose
+     * connection cannot take the message right away.
+     */
+    pub fn broadcast(&mut self, msg: &impl Serialize) {
+        if self.streams.is_empty() {
+            return;
+        }
+
+        let bytes = match serde_json::to_vec(msg) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                eprintln!("Could not encode message: {}", e);
+                return;
+            },
+        };
+
+        self.streams.retain_mut(|stream| {
+            stream.write_all(&bytes).and_then(|_| stream.flush()).is_ok()
+        });
+    }
+}
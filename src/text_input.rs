@@ -0,0 +1,89 @@
+use ggez::input::keyboard::{KeyInput, KeyMods};
+use ggez::winit::event::VirtualKeyCode;
+
+/**
+ * Single line text field fed by [ggez::event::EventHandler::text_input_event]
+ * for characters and [TextInput::key_down] for editing keys.
+ */
+#[derive(Default)]
+pub struct TextInput {
+    text: Vec<char>,
+    /// Position of the cursor in characters
+    cursor: usize,
+}
+
+impl TextInput {
+    pub fn new(text: &str) -> Self {
+        let text: Vec<char> = text.chars().collect();
+        return TextInput {
+            cursor: text.len(),
+            text,
+        };
+    }
+
+    pub fn text(&self) -> String {
+        return self.text.iter().collect();
+    }
+
+    pub fn insert(&mut self, c: char) {
+        if c.is_control() {
+            return;
+        }
+        self.text.insert(self.cursor, c);
+        self.cursor += 1;
+    }
+
+    pub fn insert_str(&mut self, s: &str) {
+        for c in s.chars() {
+            self.insert(c);
+        }
+    }
+
+    fn paste(&mut self) {
+        match arboard::Clipboard::new().and_then(|mut c| c.get_text()) {
+            Ok(text) => self.insert_str(text.trim()),
+            Err(e) => eprintln!("Could not paste: {}", e),
+        }
+    }
+
+    pub fn key_down(&mut self, input: &KeyInput) {
+        let key = match input.keycode {
+            Some(key) => key,
+            None => return,
+        };
+
+        match key {
+            VirtualKeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            VirtualKeyCode::Right
+                => self.cursor = (self.cursor + 1).min(self.text.len()),
+            VirtualKeyCode::Home => self.cursor = 0,
+            VirtualKeyCode::End => self.cursor = self.text.len(),
+
+            VirtualKeyCode::Back if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.text.remove(self.cursor);
+            },
+            VirtualKeyCode::Delete if self.cursor < self.text.len()
+                => _ = self.text.remove(self.cursor),
+
+            VirtualKeyCode::V if input.mods.contains(KeyMods::CTRL)
+                => self.paste(),
+
+            _ => (),
+        }
+    }
+
+    /**
+     * The text to show on screen, with a cursor if the field has focus.
+     */
+    pub fn display(&self, focused: bool) -> String {
+        if !focused {
+            return self.text();
+        }
+
+        let mut out: String = self.text[..self.cursor].iter().collect();
+        out.push('|');
+        out.extend(self.text[self.cursor..].iter());
+        return out;
+    }
+}
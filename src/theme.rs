@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use ggez::Context;
+use ggez::graphics::Image;
+
+/**
+ * Colors of the board squares.
+ */
+pub struct Theme {
+    pub name: &'static str,
+    pub light: [f32; 4],
+    pub dark: [f32; 4],
+}
+
+pub const THEMES: [Theme; 4] = [
+    Theme {
+        name: "classic",
+        light: [0.12, 0.4, 0., 1.],
+        dark: [0.5, 0.5, 0.5, 1.],
+    },
+    Theme {
+        name: "wood",
+        light: [0.94, 0.85, 0.71, 1.],
+        dark: [0.71, 0.53, 0.39, 1.],
+    },
+    Theme {
+        name: "ocean",
+        light: [0.87, 0.89, 0.9, 1.],
+        dark: [0.55, 0.64, 0.68, 1.],
+    },
+    Theme {
+        name: "contrast",
+        light: [0.85, 0.85, 0.85, 1.],
+        dark: [0.35, 0.35, 0.35, 1.],
+    },
+];
+
+/**
+ * Index of the theme called `name` in [THEMES], the first one if there is no
+ * such theme.
+ */
+pub fn theme_index(name: &str) -> usize {
+    return THEMES.iter().position(|t| t.name == name).unwrap_or(0);
+}
+
+/// Piece set that draws the piece letters instead of images
+pub const LETTERS: &str = "letters";
+
+const PIECE_NAMES: [&str; 6] = ["K", "Q", "R", "B", "N", "P"];
+
+/**
+ * Images for every piece, loaded from `/pieces/<name>/` in the resource path.
+ * The files are named by color and piece, e.g. `wK.png` or `bN.png`. Without
+ * images the pieces are drawn as letters.
+ */
+pub struct PieceSet {
+    pub name: String,
+    images: HashMap<(bool, String), Image>,
+}
+
+impl PieceSet {
+    /**
+     * Names of all piece sets, starting with [LETTERS].
+     */
+    pub fn available(ctx: &Context) -> Vec<String> {
+        let mut sets = vec![String::from(LETTERS)];
+        if let Ok(dirs) = ctx.fs.read_dir("/pieces") {
+            let mut names: Vec<String> = dirs
+                .filter(|dir| ctx.fs.is_dir(dir))
+                .filter_map(|dir| dir.file_name()
+                            .map(|n| n.to_string_lossy().into_owned()))
+                .collect();
+            names.sort();
+            sets.append(&mut names);
+        }
+        return sets;
+    }
+
+    /**
+     * Load the set called `name`. Falls back to letters if any image is
+     * missing.
+     */
+    pub fn load(ctx: &Context, name: &str) -> Self {
+        let letters = PieceSet {
+            name: String::from(LETTERS),
+            images: HashMap::new(),
+        };
+        if name == LETTERS {
+            return letters;
+        }
+
+        let mut images = HashMap::new();
+        for white in [true, false] {
+            for piece in PIECE_NAMES {
+                let path = format!("/pieces/{}/{}{}.png", name,
+                                   if white { "w" } else { "b" }, piece);
+                match Image::from_path(ctx, &path) {
+                    Ok(image) => {
+                        images.insert((white, String::from(piece)), image);
+                    },
+                    Err(e) => {
+                        eprintln!("Warning: could not load {}: {}", path, e);
+                        return letters;
+                    },
+                }
+            }
+        }
+
+        return PieceSet {
+            name: String::from(name),
+            images,
+        };
+    }
+
+    pub fn image(&self, white: bool, piece: &str) -> Option<&Image> {
+        return self.images.get(&(white, String::from(piece)));
+    }
+}
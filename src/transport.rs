@@ -0,0 +1,252 @@
+use std::io::{self, Read, Write, ErrorKind};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+
+use crate::connection::{CONNECT_TIMEOUT, POLL_TIMEOUT, RESPONSE_TIMEOUT};
+
+/*
+ * Reads on a transport's streams must not block for longer than a moment.
+ * When nothing has arrived they fail with `WouldBlock` or `TimedOut`, which
+ * is what [crate::connection::JsonReader] relies on to poll.
+ */
+
+/**
+ * Opens connections to a server, used by [crate::remote_engine::RemoteGame].
+ * Connecting again has to reach the same server.
+ */
+pub trait Connector {
+    type Stream: Read + Write;
+
+    fn connect(&mut self) -> io::Result<Self::Stream>;
+
+    /**
+     * Where the connection goes, for messages to the user.
+     */
+    fn describe(&self) -> String;
+}
+
+/**
+ * Streams that can be switched to fail with `WouldBlock` instead of waiting,
+ * for peers like spectators that must never hold up the game.
+ */
+pub trait Nonblocking {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+}
+
+impl Nonblocking for TcpStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        return TcpStream::set_nonblocking(self, nonblocking);
+    }
+}
+
+/**
+ * Accepts connections from clients, used by
+ * [crate::remote_host_engine::RemoteHostGame].
+ */
+pub trait Acceptor {
+    type Stream: Read + Write + Nonblocking;
+
+    /**
+     * Wait for the first client.
+     */
+    fn accept(&mut self) -> io::Result<Self::Stream>;
+
+    /**
+     * Accept a client if one is waiting, without blocking.
+     */
+    fn try_accept(&mut self) -> io::Result<Option<Self::Stream>>;
+
+    fn describe(&self) -> String;
+}
+
+fn configure_tcp(stream: &TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(POLL_TIMEOUT))?;
+    stream.set_write_timeout(Some(RESPONSE_TIMEOUT))?;
+    stream.set_nodelay(true)?;
+    return Ok(());
+}
+
+pub struct TcpConnector {
+    addr: SocketAddr,
+}
+
+impl TcpConnector {
+    pub fn new(addr: SocketAddr) -> Self {
+        return TcpConnector { addr };
+    }
+}
+
+impl Connector for TcpConnector {
+    type Stream = TcpStream;
+
+    fn connect(&mut self) -> io::Result<TcpStream> {
+        let stream = TcpStream::connect_timeout(&self.addr, CONNECT_TIMEOUT)?;
+        configure_tcp(&stream)?;
+        return Ok(stream);
+    }
+
+    fn describe(&self) -> String {
+        return self.addr.to_string();
+    }
+}
+
+pub struct TcpAcceptor {
+    listener: TcpListener,
+}
+
+impl TcpAcceptor {
+    /**
+     * Listen on `port` on all interfaces.
+     */
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(format!("0.0.0.0:{}", port))?;
+        return Ok(TcpAcceptor { listener });
+    }
+
+    /**
+     * The port actually listened on, useful after binding to port 0.
+     */
+    pub fn port(&self) -> io::Result<u16> {
+        return Ok(self.listener.local_addr()?.port());
+    }
+}
+
+impl Acceptor for TcpAcceptor {
+    type Stream = TcpStream;
+
+    fn accept(&mut self) -> io::Result<TcpStream> {
+        self.listener.set_nonblocking(false)?;
+        let (stream, _) = self.listener.accept()?;
+        configure_tcp(&stream)?;
+        return Ok(stream);
+    }
+
+    fn try_accept(&mut self) -> io::Result<Option<TcpStream>> {
+        self.listener.set_nonblocking(true)?;
+        let (stream, _) = match self.listener.accept() {
+            Ok(client) => client,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        stream.set_nonblocking(false)?;
+        configure_tcp(&stream)?;
+        return Ok(Some(stream));
+    }
+
+    fn describe(&self) -> String {
+        return match self.port() {
+            Ok(port) => format!("port {}", port),
+            Err(_) => String::from("TCP"),
+        };
+    }
+}
+
+#[cfg(unix)]
+pub use self::unix::*;
+
+#[cfg(unix)]
+mod unix {
+    use std::io::{self, ErrorKind};
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::PathBuf;
+
+    use super::{Acceptor, Connector, Nonblocking};
+    use crate::connection::{POLL_TIMEOUT, RESPONSE_TIMEOUT};
+
+    fn configure_unix(stream: &UnixStream) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(POLL_TIMEOUT))?;
+        stream.set_write_timeout(Some(RESPONSE_TIMEOUT))?;
+        return Ok(());
+    }
+
+    impl Nonblocking for UnixStream {
+        fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+            return UnixStream::set_nonblocking(self, nonblocking);
+        }
+    }
+
+    pub struct UnixConnector {
+        path: PathBuf,
+    }
+
+    impl UnixConnector {
+        pub fn new(path: impl Into<PathBuf>) -> Self {
+            return UnixConnector { path: path.into() };
+        }
+    }
+
+    impl Connector for UnixConnector {
+        type Stream = UnixStream;
+
+        fn connect(&mut self) -> io::Result<UnixStream> {
+            let stream = UnixStream::connect(&self.path)?;
+            configure_unix(&stream)?;
+            return Ok(stream);
+        }
+
+        fn describe(&self) -> String {
+            return self.path.display().to_string();
+        }
+    }
+
+    pub struct UnixAcceptor {
+        listener: UnixListener,
+        path: PathBuf,
+    }
+
+    impl UnixAcceptor {
+        /**
+         * Listen on the socket file at `path`, replacing a stale one. Fails
+         * if anything else is in the way, rather than deleting it.
+         */
+        pub fn bind(path: impl Into<PathBuf>) -> io::Result<Self> {
+            let path = path.into();
+            match std::fs::symlink_metadata(&path) {
+                Ok(meta) if meta.file_type().is_socket() => {
+                    std::fs::remove_file(&path)?;
+                },
+                Ok(_) => return Err(io::Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", path.display()),
+                )),
+                Err(e) if e.kind() == ErrorKind::NotFound => (),
+                Err(e) => return Err(e),
+            }
+            let listener = UnixListener::bind(&path)?;
+            return Ok(UnixAcceptor { listener, path });
+        }
+    }
+
+    impl Acceptor for UnixAcceptor {
+        type Stream = UnixStream;
+
+        fn accept(&mut self) -> io::Result<UnixStream> {
+            self.listener.set_nonblocking(false)?;
+            let (stream, _) = self.listener.accept()?;
+            configure_unix(&stream)?;
+            return Ok(stream);
+        }
+
+        fn try_accept(&mut self) -> io::Result<Option<UnixStream>> {
+            self.listener.set_nonblocking(true)?;
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            configure_unix(&stream)?;
+            return Ok(Some(stream));
+        }
+
+        fn describe(&self) -> String {
+            return self.path.display().to_string();
+        }
+    }
+
+    impl Drop for UnixAcceptor {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+/*
+ * Play against the computer and check that it answers every move in turn.
+ */
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sieric_chess_gui::ai_engine::*;
+use sieric_chess_gui::chess_engine::*;
+
+/// How long the computer may think about a move in the tests
+const THINKING_TIME: Duration = Duration::from_secs(30);
+
+/// White moves that stay legal whatever black answers
+const WHITE_MOVES: [ChessMove; 2] = [
+    ChessMove { from: (0, 1), to: (0, 2), capture: false, promotion: false },
+    ChessMove { from: (7, 1), to: (7, 2), capture: false, promotion: false },
+];
+
+/**
+ * Wait for the computer's move, which it looks for on a thread of its own.
+ * Returns [false] if it did not move in time.
+ */
+fn computer_moves(game: &mut AiGame) -> bool {
+    let deadline = Instant::now() + THINKING_TIME;
+    while Instant::now() < deadline {
+        if game.wait_move() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    return false;
+}
+
+#[test]
+fn computer_answers_each_move() {
+    for depth in 1..=2 {
+        let mut game = AiGame::new(true, depth);
+        assert!(!game.opponent_starts());
+        assert!(!game.wait_move(), "the computer moved for white");
+
+        for mv in WHITE_MOVES {
+            assert!(game.apply_move(&mv));
+            assert!(!game.get_player());
+            assert!(computer_moves(&mut game), "the computer did not answer");
+            assert!(game.get_player());
+        }
+    }
+}
+
+#[test]
+fn computer_starts_as_white() {
+    let mut game = AiGame::new(false, 1);
+    assert!(game.opponent_starts());
+    assert!(computer_moves(&mut game));
+    assert!(!game.get_player());
+    assert!(!game.wait_move(), "the computer moved for black");
+}
@@ -0,0 +1,125 @@
+/*
+ * Helpers shared by the tests that play positions and talk to a host over
+ * pipes. Not every test uses all of them.
+ */
+#![allow(dead_code)]
+
+use chess_network_protocol::*;
+use sieric_chess_gui::chess_engine::*;
+use sieric_chess_gui::connection::*;
+use sieric_chess_gui::local_engine::{LocalGame, Rules};
+use sieric_chess_gui::pipe::*;
+use sieric_chess_gui::protocol::*;
+use sieric_chess_gui::remote_host_engine::RemoteHostGame;
+use sieric_chess_gui::script::parse_move;
+use sieric_chess_gui::transport::*;
+
+/// How often to poll for an answer before giving up, each poll takes about a
+/// millisecond
+pub const MAX_POLLS: usize = 5000;
+
+/**
+ * The move written as in scripts, e.g. `e2e4` or `b7a8q`, for `white` (or
+ * black).
+ */
+pub fn mv(text: &str, white: bool) -> Move {
+    return parse_move(text, white).unwrap();
+}
+
+/**
+ * Play `moves` from the start by `rules`, white first.
+ */
+pub fn play_from_start(rules: Rules, moves: &[&str]) -> LocalGame {
+    let mut engine = LocalGame::with_rules(rules);
+    for (i, text) in moves.iter().enumerate() {
+        assert!(apply_proto_move(&mut engine, &mv(text, i % 2 == 0)), "{}",
+                text);
+    }
+    return engine;
+}
+
+/**
+ * Connect to a new host playing `server_color` and do the handshake by hand.
+ */
+pub fn raw_client(server_color: Color)
+    -> (RemoteHostGame<PipeAcceptor>, PipeStream) {
+    let (mut connector, acceptor) = pipe_transport();
+    return raw_client_of(&mut connector, acceptor, server_color);
+}
+
+/**
+ * Like [raw_client], for a host listening on `acceptor`.
+ */
+pub fn raw_client_of<A: Acceptor>(connector: &mut PipeConnector, acceptor: A,
+                                  server_color: Color)
+    -> (RemoteHostGame<A>, PipeStream) {
+    let mut client = connector.connect().unwrap();
+
+    /* the pipe holds the handshake until the host gets to it */
+    let c2sh = ClientToServerHandshake { server_color };
+    serde_json::to_writer(&mut client, &c2sh).unwrap();
+
+    let host = RemoteHostGame::new(acceptor).expect("host handshake");
+    let _: ServerToClientHandshake = JsonReader::default()
+        .read(&mut client, RESPONSE_TIMEOUT).unwrap();
+    return (host, client);
+}
+
+/**
+ * Let the host handle what the client sent and return its answer.
+ */
+pub fn answer<A: Acceptor>(host: &mut RemoteHostGame<A>,
+                           client: &mut PipeStream, reader: &mut JsonReader)
+    -> ServerToClient {
+    for _ in 0..MAX_POLLS {
+        host.wait_move();
+        if let Some(s2c) = reader.poll(client).unwrap() {
+            return s2c;
+        }
+    }
+    panic!("the host never answered");
+}
+
+/**
+ * Send `c2s` as the client and return the host's answer.
+ */
+pub fn exchange<A: Acceptor>(host: &mut RemoteHostGame<A>,
+                             client: &mut PipeStream,
+                             reader: &mut JsonReader, c2s: &ClientToServer)
+    -> ServerToClient {
+    serde_json::to_writer(&mut *client, c2s).unwrap();
+    return answer(host, client, reader);
+}
+
+/**
+ * Let the host make `mv` and return what the client is told.
+ */
+pub fn host_move<A: Acceptor>(host: &mut RemoteHostGame<A>,
+                              client: &mut PipeStream,
+                              reader: &mut JsonReader, mv: &ChessMove)
+    -> ServerToClient {
+    assert!(host.apply_move(mv), "the host could not move");
+    return reader.read(client, RESPONSE_TIMEOUT).unwrap();
+}
+
+/**
+ * Play `moves` from the start, white first, sending the client's moves by
+ * hand and letting the host make its own. Every move has to be accepted.
+ */
+pub fn alternate<A: Acceptor>(host: &mut RemoteHostGame<A>,
+                              client: &mut PipeStream,
+                              reader: &mut JsonReader, moves: &[&str]) {
+    /* the host waits first if the client plays white */
+    let client_white = host.opponent_starts();
+    for (i, text) in moves.iter().enumerate() {
+        let white = i % 2 == 0;
+        let s2c = if white == client_white {
+            let c2s = ClientToServer::Move(mv(text, white));
+            exchange(host, client, reader, &c2s)
+        } else {
+            let own = proto_to_chess_move(&mv(text, white)).unwrap();
+            host_move(host, client, reader, &own)
+        };
+        assert!(matches!(s2c, ServerToClient::State { .. }), "{}", text);
+    }
+}
@@ -0,0 +1,357 @@
+/*
+ * Feed the message reader, the host and the client broken, oversized and
+ * random input and check that they refuse it without panicking.
+ */
+
+mod common;
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::thread;
+
+use chess_network_protocol::*;
+use sieric_chess_gui::chess_engine::*;
+use sieric_chess_gui::connection::*;
+use sieric_chess_gui::local_engine::{LocalGame, Rules};
+use sieric_chess_gui::pipe::*;
+use sieric_chess_gui::protocol::*;
+use sieric_chess_gui::remote_engine::RemoteGame;
+use sieric_chess_gui::remote_host_engine::{MAX_STRIKES, WaitingHost};
+use sieric_chess_gui::transport::*;
+
+use common::*;
+
+/// How many random inputs each fuzz test tries
+const ROUNDS: usize = 2000;
+
+/**
+ * Small xorshift generator, the tests only need repeatable noise.
+ */
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        return self.0;
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        return (self.next() % n as u64) as usize;
+    }
+}
+
+/**
+ * Hands out its data in random pieces with nothing to read in between, like
+ * a slow connection, and then reports the end of the stream.
+ */
+struct Trickle {
+    data: Vec<u8>,
+    pos: usize,
+    rng: Rng,
+}
+
+impl Trickle {
+    fn new(data: Vec<u8>, seed: u64) -> Self {
+        return Trickle { data, pos: 0, rng: Rng(seed) };
+    }
+}
+
+impl Read for Trickle {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.data.len() {
+            return Ok(0);
+        }
+        if self.rng.below(3) == 0 {
+            return Err(io::Error::from(ErrorKind::WouldBlock));
+        }
+
+        let n = (1 + self.rng.below(64))
+            .min(buf.len())
+            .min(self.data.len() - self.pos);
+        buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+        self.pos += n;
+        return Ok(n);
+    }
+}
+
+/**
+ * Read messages until the stream ends. Returns the messages and how many
+ * were refused, failing the test on any other error.
+ */
+fn read_all(stream: &mut impl Read) -> (Vec<ClientToServer>, usize) {
+    let mut reader = JsonReader::default();
+    let mut messages = Vec::new();
+    let mut refused = 0;
+    loop {
+        match reader.poll::<ClientToServer, _>(stream) {
+            Ok(Some(msg)) => messages.push(msg),
+            Ok(None) => (),
+            Err(e) if e.kind() == ErrorKind::InvalidData => refused += 1,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    }
+    return (messages, refused);
+}
+
+fn valid_messages() -> Vec<String> {
+    let mv = Move {
+        start_x: 4, start_y: 1, end_x: 4, end_y: 3,
+        promotion: Piece::None,
+    };
+    return [
+        ClientToServer::Move(mv),
+        ClientToServer::Resign,
+        ClientToServer::Draw,
+    ].iter().map(|msg| serde_json::to_string(msg).unwrap()).collect();
+}
+
+#[test]
+fn random_bytes_are_refused() {
+    let mut rng = Rng(0x2545f4914f6cdd1d);
+    for round in 0..ROUNDS {
+        let len = rng.below(512);
+        let data = (0..len).map(|_| rng.next() as u8).collect();
+        read_all(&mut Trickle::new(data, round as u64 + 1));
+    }
+}
+
+#[test]
+fn mutated_messages_are_refused() {
+    let valid = valid_messages();
+    let mut rng = Rng(0x9e3779b97f4a7c15);
+    for round in 0..ROUNDS {
+        let mut data = valid[rng.below(valid.len())].clone().into_bytes();
+        for _ in 0..1 + rng.below(4) {
+            let i = rng.below(data.len());
+            match rng.below(3) {
+                0 => data[i] = rng.next() as u8,
+                1 => data.insert(i, b"{}[]\":,0"[rng.below(8)]),
+                _ => {
+                    data.remove(i);
+                },
+            }
+            if data.is_empty() {
+                break;
+            }
+        }
+
+        read_all(&mut Trickle::new(data, round as u64 + 1));
+    }
+}
+
+#[test]
+fn reading_goes_on_after_a_bad_message() {
+    /* valid JSON, but not a message */
+    let mut data = br#"{"Move": 42}"#.to_vec();
+    data.extend_from_slice(valid_messages()[1].as_bytes());
+
+    let (messages, refused) = read_all(&mut Trickle::new(data, 7));
+    assert_eq!(refused, 1);
+    assert!(matches!(messages[..], [ClientToServer::Resign]));
+}
+
+#[test]
+fn messages_after_broken_json_are_kept() {
+    let valid = valid_messages();
+    let mut data = br#"{"Move": ]"#.to_vec();
+    data.extend_from_slice(valid[0].as_bytes());
+    data.extend_from_slice(b"]");
+    data.extend_from_slice(valid[1].as_bytes());
+
+    for seed in 1..20 {
+        let (messages, refused) = read_all(&mut Trickle::new(data.clone(),
+                                                             seed));
+        assert_eq!(refused, 2);
+        assert!(matches!(messages[..], [
+            ClientToServer::Move(_),
+            ClientToServer::Resign,
+        ]), "seed {}", seed);
+    }
+}
+
+#[test]
+fn oversized_messages_are_refused() {
+    let mut data = b"[".to_vec();
+    while data.len() <= MAX_MESSAGE_SIZE {
+        data.extend_from_slice(b"1,");
+    }
+
+    let mut reader = JsonReader::default();
+    let mut stream = io::Cursor::new(data);
+    let refused = loop {
+        match reader.poll::<ClientToServer, _>(&mut stream) {
+            Ok(_) => (),
+            Err(e) => break e,
+        }
+    };
+    assert_eq!(refused.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn host_answers_garbage_with_errors() {
+    let (mut host, mut client) = raw_client(Color::Black);
+    let mut reader = JsonReader::default();
+
+    client.write_all(b"{\"Move\": nonsense}").unwrap();
+    let s2c = answer(&mut host, &mut client, &mut reader);
+    assert!(matches!(s2c, ServerToClient::Error { .. }));
+    assert!(host.is_connected());
+
+    /* the game goes on */
+    let mv = Move {
+        start_x: 4, start_y: 1, end_x: 4, end_y: 3,
+        promotion: Piece::None,
+    };
+    serde_json::to_writer(&mut client, &ClientToServer::Move(mv)).unwrap();
+    let s2c = answer(&mut host, &mut client, &mut reader);
+    assert!(matches!(s2c, ServerToClient::State { .. }));
+}
+
+#[test]
+fn host_cuts_off_clients_that_keep_failing() {
+    let (mut host, mut client) = raw_client(Color::Black);
+    let mut reader = JsonReader::default();
+
+    let illegal = Move {
+        start_x: 4, start_y: 1, end_x: 4, end_y: 5,
+        promotion: Piece::None,
+    };
+    for _ in 0..MAX_STRIKES {
+        serde_json::to_writer(&mut client, &ClientToServer::Move(illegal))
+            .unwrap();
+        let s2c = answer(&mut host, &mut client, &mut reader);
+        assert!(matches!(s2c, ServerToClient::Error { .. }));
+    }
+    assert!(!host.is_connected());
+}
+
+#[test]
+fn host_answers_draw_offers_without_a_strike() {
+    let (mut host, mut client) = raw_client(Color::Black);
+    let mut reader = JsonReader::default();
+
+    for _ in 0..MAX_STRIKES + 1 {
+        let s2c = exchange(&mut host, &mut client, &mut reader,
+                           &ClientToServer::Draw);
+        assert!(matches!(s2c, ServerToClient::Error { .. }));
+    }
+    assert!(host.is_connected());
+}
+
+#[test]
+fn host_refuses_moves_out_of_turn() {
+    let (mut host, mut client) = raw_client(Color::White);
+    let mut reader = JsonReader::default();
+
+    /* the client plays black but moves before the host */
+    let black = Move {
+        start_x: 4, start_y: 6, end_x: 4, end_y: 4,
+        promotion: Piece::None,
+    };
+    serde_json::to_writer(&mut client, &ClientToServer::Move(black)).unwrap();
+
+    let white = ChessMove {
+        from: (4, 1),
+        to: (4, 3),
+        capture: false,
+        promotion: false,
+    };
+    assert!(host.apply_move(&white));
+    assert_eq!(host.get_piece(&(4, 4)), (true, String::from(" ")));
+
+    let s2c = reader.read(&mut client, RESPONSE_TIMEOUT).unwrap();
+    assert!(matches!(s2c, ServerToClient::Error { .. }));
+    let s2c = reader.read(&mut client, RESPONSE_TIMEOUT).unwrap();
+    assert!(matches!(s2c, ServerToClient::State { .. }));
+}
+
+#[test]
+fn waiting_host_skips_clients_without_a_handshake() {
+    let (mut connector, acceptor) = pipe_transport();
+    let mut host = WaitingHost::new(acceptor, Rules::default());
+    assert!(host.poll().unwrap().is_none());
+
+    /* the first client leaves before its handshake */
+    drop(connector.connect().unwrap());
+    assert!(host.poll().unwrap().is_none());
+
+    let mut client = connector.connect().unwrap();
+    let c2sh = ClientToServerHandshake { server_color: Color::Black };
+    serde_json::to_writer(&mut client, &c2sh).unwrap();
+    let game = (0..MAX_POLLS)
+        .find_map(|_| host.poll().unwrap())
+        .expect("the host never took the handshake");
+
+    let _: ServerToClientHandshake
+        = JsonReader::default().read(&mut client, RESPONSE_TIMEOUT).unwrap();
+    /* the client plays white */
+    assert!(game.opponent_starts());
+    assert!(host.poll().is_err());
+}
+
+/// On the board in every way but its target file
+const OFF_BOARD: Move = Move {
+    start_x: 4, start_y: 1, end_x: 8, end_y: 3,
+    promotion: Piece::None,
+};
+
+/**
+ * Play a server that offers `moves` in the handshake, and answers the
+ * client's first move, if it makes one, with `move_made`.
+ */
+fn dishonest_server(mut acceptor: PipeAcceptor, moves: Vec<Move>,
+                    move_made: Move) -> thread::JoinHandle<()> {
+    return thread::spawn(move || {
+        let mut stream = acceptor.accept().unwrap();
+        let mut reader = JsonReader::default();
+        let engine = LocalGame::new();
+
+        let _: ClientToServerHandshake
+            = reader.read(&mut stream, RESPONSE_TIMEOUT).unwrap();
+        let s2ch = ServerToClientHandshake {
+            features: vec![Features::Castling, Features::EnPassant,
+                           Features::Promotion],
+            board: proto_board(&engine),
+            moves,
+            joever: Joever::Ongoing,
+        };
+        serde_json::to_writer(&mut stream, &s2ch).unwrap();
+
+        if let Ok(ClientToServer::Move(_))
+            = reader.read(&mut stream, RESPONSE_TIMEOUT) {
+            let s2c = ServerToClient::State {
+                board: proto_board(&engine),
+                moves: proto_moves(&engine),
+                joever: Joever::Ongoing,
+                move_made,
+            };
+            serde_json::to_writer(&mut stream, &s2c).unwrap();
+        }
+    });
+}
+
+#[test]
+fn client_refuses_moves_off_the_board() {
+    let (connector, acceptor) = pipe_transport();
+    let server = dishonest_server(acceptor, vec![OFF_BOARD], OFF_BOARD);
+    let refused = RemoteGame::new(connector, Color::White).err()
+        .expect("the client took a handshake with a move off the board");
+    assert_eq!(refused.kind(), ErrorKind::InvalidData);
+    server.join().unwrap();
+
+    let (connector, acceptor) = pipe_transport();
+    let moves = proto_moves(&LocalGame::new());
+    let server = dishonest_server(acceptor, moves, OFF_BOARD);
+    let mut game = RemoteGame::new(connector, Color::White).unwrap();
+    let e2e4 = ChessMove {
+        from: (4, 1),
+        to: (4, 3),
+        capture: false,
+        promotion: false,
+    };
+    assert!(!game.apply_move(&e2e4));
+    assert!(!game.is_connected());
+    server.join().unwrap();
+}
@@ -0,0 +1,168 @@
+/*
+ * Try illegal moves against the engine and a host, and check that each one
+ * is refused with the reason that fits it.
+ */
+
+mod common;
+
+use chess_network_protocol::*;
+use sieric_chess_gui::chess_engine::*;
+use sieric_chess_gui::connection::JsonReader;
+use sieric_chess_gui::local_engine::{IllegalMove, LocalGame, Rules};
+use sieric_chess_gui::pipe::*;
+use sieric_chess_gui::protocol::*;
+use sieric_chess_gui::remote_host_engine::RemoteHostGame;
+
+use common::*;
+
+/// Leaves white free to castle short, but with f1 covered by the bishop on a6
+const F1_ATTACKED: [&str; 8] = [
+    "e2e4", "b7b6", "g1f3", "c8a6", "g2g3", "e7e6", "f1g2", "b8c6",
+];
+
+/// Leaves white to move with a pawn on e5 that can take d5 en passant
+const EN_PASSANT: [&str; 4] = ["e2e4", "a7a6", "e4e5", "d7d5"];
+
+/// Leaves white to move after the pawn on d5 had its chance to be taken
+const EN_PASSANT_MISSED: [&str; 6] = [
+    "e2e4", "a7a6", "e4e5", "d7d5", "a2a3", "a6a5",
+];
+
+/// Leaves black in check from the bishop on b5
+const BLACK_IN_CHECK: [&str; 3] = ["e2e4", "d7d5", "f1b5"];
+
+/// Leaves white free to castle short
+const KINGSIDE_CLEAR: [&str; 6] = [
+    "g1f3", "a7a6", "g2g3", "a6a5", "f1g2", "a5a4",
+];
+
+/// Fool's mate, without the mating move
+const FOOLS_MATE: [&str; 3] = ["f2f3", "e7e5", "g2g4"];
+const MATE: &str = "d8h4";
+
+/**
+ * Return why the player to move in `engine` may not make `text`.
+ */
+fn reason(engine: &LocalGame, text: &str) -> IllegalMove {
+    let proto = mv(text, engine.get_player());
+    return check_proto_move(engine, &proto)
+        .expect_err(&format!("{} was accepted", text));
+}
+
+#[test]
+fn moves_need_a_piece_of_the_player_to_move() {
+    let engine = LocalGame::new();
+    assert_eq!(reason(&engine, "e3e4"), IllegalMove::NoPiece);
+    assert_eq!(reason(&engine, "e7e5"), IllegalMove::NoPiece);
+}
+
+#[test]
+fn pieces_move_by_their_rules() {
+    let engine = LocalGame::new();
+    assert_eq!(reason(&engine, "e2e5"), IllegalMove::IllegalForPiece);
+    assert_eq!(reason(&engine, "g1g3"), IllegalMove::IllegalForPiece);
+    assert_eq!(reason(&engine, "d1d3"), IllegalMove::IllegalForPiece);
+
+    /* a pawn only goes diagonally to take something */
+    assert_eq!(reason(&engine, "e2d3"), IllegalMove::IllegalForPiece);
+}
+
+#[test]
+fn moves_must_not_leave_the_king_in_check() {
+    let engine = play_from_start(Rules::default(), &BLACK_IN_CHECK);
+    assert_eq!(reason(&engine, "a7a6"), IllegalMove::LeavesKingInCheck);
+}
+
+#[test]
+fn moves_stay_on_the_board() {
+    let engine = LocalGame::new();
+    /* truncated to 32 bits this would be e2e4 */
+    let wide = Move { start_x: (1 << 32) + 4, ..mv("e2e4", true) };
+    assert_eq!(check_proto_move(&engine, &wide), Err(IllegalMove::OffBoard));
+    assert!(proto_to_chess_move(&wide).is_none());
+
+    let far = Move { end_x: 200, end_y: usize::MAX, ..mv("e2e4", true) };
+    assert_eq!(check_proto_move(&engine, &far), Err(IllegalMove::OffBoard));
+    assert_eq!(move_name(&far), format!("e2(200, {})", usize::MAX));
+}
+
+#[test]
+fn castling_reasons() {
+    let engine = LocalGame::new();
+    assert_eq!(reason(&engine, "e1g1"), IllegalMove::CannotCastle);
+
+    let engine = play_from_start(Rules::default(), &F1_ATTACKED);
+    assert_eq!(reason(&engine, "e1g1"), IllegalMove::CastlingThroughCheck);
+
+    let rules = Rules { castling: false, ..Rules::default() };
+    let engine = play_from_start(Rules::default(), &KINGSIDE_CLEAR);
+    assert!(check_proto_move(&engine, &mv("e1g1", true)).is_ok());
+    let engine = play_from_start(rules, &KINGSIDE_CLEAR);
+    assert_eq!(reason(&engine, "e1g1"), IllegalMove::CastlingDisabled);
+}
+
+#[test]
+fn en_passant_reasons() {
+    let engine = play_from_start(Rules::default(), &EN_PASSANT);
+    assert!(check_proto_move(&engine, &mv("e5d6", true)).is_ok());
+
+    let rules = Rules { en_passant: false, ..Rules::default() };
+    let engine = play_from_start(rules, &EN_PASSANT);
+    assert_eq!(reason(&engine, "e5d6"), IllegalMove::EnPassantDisabled);
+
+    let engine = play_from_start(Rules::default(), &EN_PASSANT_MISSED);
+    assert_eq!(reason(&engine, "e5d6"), IllegalMove::NoEnPassant);
+    assert_eq!(reason(&engine, "e5f6"), IllegalMove::IllegalForPiece);
+}
+
+/**
+ * Send `text` as the client's move and return the message of the error the
+ * host answers with.
+ */
+fn host_error(host: &mut RemoteHostGame<PipeAcceptor>,
+              client: &mut PipeStream, reader: &mut JsonReader,
+              text: &str, white: bool) -> String {
+    let c2s = ClientToServer::Move(mv(text, white));
+    return match exchange(host, client, reader, &c2s) {
+        ServerToClient::Error { message, .. } => message,
+        _ => panic!("the host accepted {}", text),
+    };
+}
+
+#[test]
+fn host_explains_refused_moves() {
+    let (mut host, mut client) = raw_client(Color::White);
+    let mut reader = JsonReader::default();
+
+    /* white, the host, moves first */
+    let message = host_error(&mut host, &mut client, &mut reader, "e7e5",
+                             false);
+    assert_eq!(message, IllegalMove::NotYourTurn.to_string());
+
+    let e2e4 = proto_to_chess_move(&mv("e2e4", true)).unwrap();
+    host_move(&mut host, &mut client, &mut reader, &e2e4);
+    let reasons = [
+        ("e6e5", IllegalMove::NoPiece),
+        ("e7e4", IllegalMove::IllegalForPiece),
+        ("e8g8", IllegalMove::CannotCastle),
+    ];
+    for (text, reason) in reasons {
+        let message = host_error(&mut host, &mut client, &mut reader, text,
+                                 false);
+        assert_eq!(message, reason.to_string(), "{}", text);
+    }
+}
+
+#[test]
+fn host_refuses_moves_after_the_game() {
+    let (mut host, mut client) = raw_client(Color::Black);
+    let mut reader = JsonReader::default();
+
+    alternate(&mut host, &mut client, &mut reader, &FOOLS_MATE);
+    let mate = proto_to_chess_move(&mv(MATE, false)).unwrap();
+    host_move(&mut host, &mut client, &mut reader, &mate);
+
+    let message = host_error(&mut host, &mut client, &mut reader, "a2a3",
+                             true);
+    assert_eq!(message, IllegalMove::GameOver.to_string());
+}
@@ -0,0 +1,124 @@
+/*
+ * Promote a pawn to each of the four pieces, through the engine and through a
+ * host, and check that exactly the requested piece ends up on the board.
+ */
+
+mod common;
+
+use chess_network_protocol::*;
+use sieric_chess_gui::chess_engine::*;
+use sieric_chess_gui::connection::JsonReader;
+use sieric_chess_gui::local_engine::{IllegalMove, LocalGame, Rules};
+use sieric_chess_gui::pipe::*;
+use sieric_chess_gui::protocol::*;
+use sieric_chess_gui::remote_host_engine::RemoteHostGame;
+
+use common::*;
+
+/// Leaves white to move with a pawn on b7 that can take the rook on a8
+const BEFORE_PROMOTION: [&str; 8] = [
+    "a2a4", "b7b5", "a4b5", "a7a6", "b5a6", "c8b7", "a6b7", "b8c6",
+];
+const PROMOTION: &str = "b7a8";
+
+const PIECES: [(char, Piece, &str); 4] = [
+    ('q', Piece::WhiteQueen, "Q"),
+    ('r', Piece::WhiteRook, "R"),
+    ('b', Piece::WhiteBishop, "B"),
+    ('n', Piece::WhiteKnight, "N"),
+];
+
+fn promotion_position() -> LocalGame {
+    return play_from_start(Rules::default(), &BEFORE_PROMOTION);
+}
+
+#[test]
+fn all_promotions_are_offered() {
+    let engine = promotion_position();
+    let moves = proto_moves(&engine);
+    for (letter, _, _) in PIECES {
+        let promotion = mv(&format!("{}{}", PROMOTION, letter), true);
+        assert!(moves.contains(&promotion), "{}{}", PROMOTION, letter);
+    }
+
+    let bare = mv(PROMOTION, true);
+    assert!(!moves.contains(&bare));
+    let black = Move { promotion: Piece::BlackQueen, ..bare };
+    assert!(!moves.contains(&black));
+}
+
+#[test]
+fn engine_applies_the_requested_piece() {
+    for (letter, piece, name) in PIECES {
+        let mut engine = promotion_position();
+        let promotion = mv(&format!("{}{}", PROMOTION, letter), true);
+        assert!(apply_proto_move(&mut engine, &promotion));
+        assert_eq!(proto_board(&engine)[7][0], piece);
+        assert_eq!(engine.get_piece(&(0, 7)), (true, String::from(name)));
+    }
+}
+
+#[test]
+fn engine_refuses_bad_promotions() {
+    let bare = mv(PROMOTION, true);
+    let wrong = [
+        bare,
+        Move { promotion: Piece::BlackRook, ..bare },
+        Move { promotion: Piece::WhitePawn, ..bare },
+        Move { promotion: Piece::WhiteKing, ..bare },
+    ];
+    for promotion in wrong {
+        let mut engine = promotion_position();
+        assert_eq!(check_proto_move(&engine, &promotion),
+                   Err(IllegalMove::BadPromotion));
+        assert!(!apply_proto_move(&mut engine, &promotion));
+    }
+
+    /* a move that does not promote cannot ask for a piece */
+    let engine = promotion_position();
+    let pawn = Move { promotion: Piece::WhiteQueen, ..mv("h2h3", true) };
+    assert_eq!(check_proto_move(&engine, &pawn),
+               Err(IllegalMove::BadPromotion));
+}
+
+/**
+ * Start a host playing black against a client driven by hand, and play up to
+ * the promotion.
+ */
+fn host_at_promotion() -> (RemoteHostGame<PipeAcceptor>, PipeStream,
+                           JsonReader) {
+    let (mut host, mut client) = raw_client(Color::Black);
+    let mut reader = JsonReader::default();
+    alternate(&mut host, &mut client, &mut reader, &BEFORE_PROMOTION);
+    return (host, client, reader);
+}
+
+#[test]
+fn host_applies_the_requested_piece() {
+    for (letter, piece, name) in PIECES {
+        let (mut host, mut client, mut reader) = host_at_promotion();
+        let promotion = mv(&format!("{}{}", PROMOTION, letter), true);
+        let c2s = ClientToServer::Move(promotion);
+        match exchange(&mut host, &mut client, &mut reader, &c2s) {
+            ServerToClient::State { board, move_made, .. } => {
+                assert_eq!(board[7][0], piece);
+                assert_eq!(move_made, promotion);
+            },
+            _ => panic!("the host refused {}{}", PROMOTION, letter),
+        }
+        assert_eq!(host.get_piece(&(0, 7)), (true, String::from(name)));
+    }
+}
+
+#[test]
+fn host_refuses_a_promotion_without_a_piece() {
+    let (mut host, mut client, mut reader) = host_at_promotion();
+    let c2s = ClientToServer::Move(mv(PROMOTION, true));
+    match exchange(&mut host, &mut client, &mut reader, &c2s) {
+        ServerToClient::Error { board, message, .. } => {
+            assert_eq!(board[6][1], Piece::WhitePawn);
+            assert_eq!(message, IllegalMove::BadPromotion.to_string());
+        },
+        _ => panic!("the host let a promotion without a piece through"),
+    }
+}
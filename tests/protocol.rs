@@ -0,0 +1,324 @@
+/*
+ * Play scripted games between a RemoteHostGame and a RemoteGame and check that
+ * both sides agree on the board, the moves and the result after every move.
+ */
+
+use std::net::SocketAddr;
+use std::thread;
+
+use chess_network_protocol::Color;
+use sieric_chess_gui::chess_engine::*;
+use sieric_chess_gui::pipe::*;
+use sieric_chess_gui::remote_engine::RemoteGame;
+use sieric_chess_gui::remote_host_engine::RemoteHostGame;
+use sieric_chess_gui::transport::*;
+
+/// How often to poll for the opponent's move before giving up, each poll
+/// takes about a millisecond
+const MAX_POLLS: usize = 5000;
+
+/**
+ * Parse a square like `e4`.
+ */
+fn square(name: &str) -> ChessLoc {
+    let name = name.as_bytes();
+    return ((name[0] - b'a') as i32, (name[1] - b'1') as i32);
+}
+
+/**
+ * Parse a move in coordinate notation like `e2e4` or `g7h8q`.
+ */
+fn chess_move(mv: &str) -> ChessMove {
+    return ChessMove {
+        from: square(&mv[0..2]),
+        to: square(&mv[2..4]),
+        capture: false,
+        promotion: mv.len() > 4,
+    };
+}
+
+fn state_name(state: ChessState) -> &'static str {
+    return match state {
+        ChessState::Ongoing => "ongoing",
+        ChessState::JoeverIndeterminate => "indeterminate",
+        ChessState::JoeverDraw => "draw",
+        ChessState::JoeverWhite => "white",
+        ChessState::JoeverBlack => "black",
+    };
+}
+
+struct Match<A: Acceptor, C: Connector> {
+    host: RemoteHostGame<A>,
+    client: RemoteGame<C>,
+    client_white: bool,
+}
+
+impl<A, C> Match<A, C>
+    where A: Acceptor, C: Connector + Send + 'static, C::Stream: Send {
+    /**
+     * Connect a client playing `client_white` to a host. The handshake needs
+     * both sides at once, so the client connects from another thread.
+     */
+    fn start(acceptor: A, connector: C, client_white: bool) -> Self {
+        let color = if client_white { Color::White } else { Color::Black };
+        let client = thread::spawn(move || RemoteGame::new(connector, color));
+        let host = RemoteHostGame::new(acceptor).expect("host handshake");
+        let client = client.join().unwrap().expect("client handshake");
+
+        let mut game = Match { host, client, client_white };
+        game.assert_agree();
+        return game;
+    }
+
+    /**
+     * Let whoever is to move play `mv`. Returns [false] if it was rejected.
+     */
+    fn play(&mut self, mv: &str) -> bool {
+        let mv = chess_move(mv);
+        let ok = if self.host.get_player() == self.client_white {
+            self.client_plays(&mv)
+        } else {
+            self.host_plays(&mv)
+        };
+
+        self.assert_agree();
+        return ok;
+    }
+
+    fn client_plays(&mut self, mv: &ChessMove) -> bool {
+        let client = &mut self.client;
+        let host = &mut self.host;
+
+        /* the client blocks on the answer, which the host only sends when
+         * polled */
+        return thread::scope(|s| {
+            let handle = s.spawn(move || client.apply_move(mv));
+            let mut host_moved = false;
+            while !handle.is_finished() {
+                host_moved |= host.wait_move();
+            }
+
+            let client_moved = handle.join().unwrap();
+            assert_eq!(client_moved, host_moved, "host and client disagree");
+            client_moved
+        });
+    }
+
+    fn host_plays(&mut self, mv: &ChessMove) -> bool {
+        if !self.host.apply_move(mv) {
+            return false;
+        }
+
+        for _ in 0..MAX_POLLS {
+            if self.client.wait_move() {
+                return true;
+            }
+        }
+        panic!("the client never got the host's move");
+    }
+
+    fn play_all(&mut self, moves: &[&str]) {
+        for mv in moves {
+            assert!(self.play(mv), "{} was rejected", mv);
+        }
+    }
+
+    fn assert_agree(&mut self) {
+        assert!(self.host.is_connected());
+        assert!(self.client.is_connected());
+
+        for y in 0..8 {
+            for x in 0..8 {
+                let loc = (x, y);
+                assert_eq!(
+                    self.host.get_piece(&loc),
+                    self.client.get_piece(&loc),
+                    "pieces differ at {:?}", loc,
+                );
+
+                let mut host: Vec<_> = self.host.get_moves(&loc).into_iter()
+                    .map(|(to, mv)| (to, mv.promotion)).collect();
+                let mut client: Vec<_> = self.client.get_moves(&loc)
+                    .into_iter().map(|(to, mv)| (to, mv.promotion)).collect();
+                host.sort();
+                client.sort();
+                assert_eq!(host, client, "moves differ from {:?}", loc);
+            }
+        }
+
+        assert_eq!(
+            state_name(self.host.get_state()),
+            state_name(self.client.get_state()),
+        );
+    }
+
+    fn piece(&mut self, name: &str) -> (bool, String) {
+        return self.client.get_piece(&square(name));
+    }
+
+    fn state(&mut self) -> &'static str {
+        return state_name(self.client.get_state());
+    }
+}
+
+fn pipe_match(client_white: bool) -> Match<PipeAcceptor, PipeConnector> {
+    let (connector, acceptor) = pipe_transport();
+    return Match::start(acceptor, connector, client_white);
+}
+
+fn empty() -> (bool, String) {
+    return (true, String::from(" "));
+}
+
+fn piece(white: bool, name: &str) -> (bool, String) {
+    return (white, String::from(name));
+}
+
+#[test]
+fn castling() {
+    for client_white in [true, false] {
+        let mut game = pipe_match(client_white);
+        game.play_all(&[
+            "e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "f8c5",
+            "e1g1", "g8f6", "d2d3", "e8g8",
+        ]);
+
+        assert_eq!(game.piece("g1"), piece(true, "K"));
+        assert_eq!(game.piece("f1"), piece(true, "R"));
+        assert_eq!(game.piece("h1"), empty());
+        assert_eq!(game.piece("g8"), piece(false, "K"));
+        assert_eq!(game.piece("f8"), piece(false, "R"));
+        assert_eq!(game.piece("h8"), empty());
+        assert_eq!(game.state(), "ongoing");
+    }
+}
+
+#[test]
+fn en_passant() {
+    for client_white in [true, false] {
+        let mut game = pipe_match(client_white);
+        game.play_all(&["e2e4", "a7a6", "e4e5", "d7d5", "e5d6"]);
+
+        assert_eq!(game.piece("d6"), piece(true, "P"));
+        assert_eq!(game.piece("d5"), empty());
+        assert_eq!(game.piece("e5"), empty());
+    }
+}
+
+#[test]
+fn promotion() {
+    for client_white in [true, false] {
+        let mut game = pipe_match(client_white);
+        game.play_all(&[
+            "h2h4", "g7g5", "h4g5", "h7h6", "g5h6", "f8g7",
+            "h6g7", "g8f6", "g7h8q",
+        ]);
+
+        assert_eq!(game.piece("h8"), piece(true, "Q"));
+        assert_eq!(game.piece("g7"), empty());
+    }
+}
+
+#[test]
+fn illegal_moves_are_rejected() {
+    let mut game = pipe_match(true);
+
+    /* too far, not a piece of ours, empty square */
+    assert!(!game.play("e2e5"));
+    assert!(!game.play("e7e5"));
+    assert!(!game.play("e4e5"));
+    assert_eq!(game.piece("e2"), piece(true, "P"));
+    assert!(game.client.take_message().is_some());
+
+    /* the game goes on normally afterwards */
+    game.play_all(&["e2e4", "e7e5"]);
+    assert_eq!(game.piece("e4"), piece(true, "P"));
+
+    /* the pawn on h7 is in the way */
+    game.play_all(&["d1h5", "b8c6"]);
+    assert!(!game.play("h5h8"));
+    assert!(game.play("h5f7"));
+}
+
+#[test]
+fn checkmate() {
+    /* fool's mate, black wins */
+    for client_white in [true, false] {
+        let mut game = pipe_match(client_white);
+        game.play_all(&["f2f3", "e7e5", "g2g4", "d8h4"]);
+        assert_eq!(game.state(), "black");
+    }
+
+    /* scholar's mate, white wins */
+    for client_white in [true, false] {
+        let mut game = pipe_match(client_white);
+        game.play_all(&["e2e4", "e7e5", "f1c4", "b8c6", "d1h5", "g8f6",
+                        "h5f7"]);
+        assert_eq!(game.state(), "white");
+    }
+}
+
+#[test]
+fn stalemate() {
+    /* Sam Loyd's ten move stalemate */
+    for client_white in [true, false] {
+        let mut game = pipe_match(client_white);
+        game.play_all(&[
+            "e2e3", "a7a5", "d1h5", "a8a6", "h5a5", "h7h5",
+            "h2h4", "a6h6", "a5c7", "f7f6", "c7d7", "e8f7",
+            "d7b7", "d8d3", "b7b8", "d3h7", "b8c8", "f7g6",
+            "c8e6",
+        ]);
+        assert_eq!(game.state(), "draw");
+    }
+}
+
+#[test]
+fn over_tcp() {
+    let acceptor = TcpAcceptor::bind(0).unwrap();
+    let addr = SocketAddr::from(([127, 0, 0, 1], acceptor.port().unwrap()));
+    let mut game = Match::start(acceptor, TcpConnector::new(addr), false);
+
+    game.play_all(&["e2e4", "e7e5", "g1f3", "b8c6"]);
+    assert_eq!(game.piece("f3"), piece(true, "N"));
+}
+
+#[test]
+fn spectators_see_every_move() {
+    let mut game = pipe_match(true);
+    let (connector, acceptor) = pipe_transport();
+    game.host.allow_spectators(acceptor);
+
+    /* the host lets spectators in while it waits for the client */
+    let watcher = thread::spawn(move || RemoteGame::spectate(connector));
+    while !watcher.is_finished() {
+        game.host.wait_move();
+    }
+    let mut spectator = watcher.join().unwrap().expect("spectator handshake");
+    assert!(spectator.is_spectating());
+
+    let moves = ["e2e4", "e7e5", "g1f3", "b8c6"];
+    game.play_all(&moves);
+
+    let mut seen = 0;
+    for _ in 0..MAX_POLLS {
+        if seen == moves.len() {
+            break;
+        }
+        if spectator.wait_move() {
+            seen += 1;
+        }
+    }
+    assert_eq!(seen, moves.len());
+
+    for y in 0..8 {
+        for x in 0..8 {
+            assert_eq!(spectator.get_piece(&(x, y)),
+                       game.host.get_piece(&(x, y)));
+        }
+    }
+
+    /* watching only */
+    assert!(spectator.get_moves(&square("d2")).is_empty());
+    assert!(!spectator.apply_move(&chess_move("d2d4")));
+}
@@ -0,0 +1,137 @@
+/*
+ * Record a game at a host and play the client's side of it back to another
+ * host, which has to answer exactly as recorded.
+ */
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use chess_network_protocol::*;
+use serde_json::Value;
+use sieric_chess_gui::chess_engine::*;
+use sieric_chess_gui::connection::JsonReader;
+use sieric_chess_gui::pipe::*;
+use sieric_chess_gui::protocol::*;
+use sieric_chess_gui::recorder::{self, Direction, Record, Recorder};
+use sieric_chess_gui::recorder::{RecordingAcceptor, recorded_by_client};
+use sieric_chess_gui::remote_host_engine::RemoteHostGame;
+use sieric_chess_gui::transport::*;
+
+use common::*;
+
+/// The client plays white and tries an illegal move first
+const ILLEGAL: &str = "e2e5";
+const MOVES: [&str; 3] = ["e2e4", "e7e5", "d2d4"];
+
+const REPLAY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/**
+ * A recording file of its own for each test.
+ */
+fn recording_path(name: &str) -> PathBuf {
+    return std::env::temp_dir()
+        .join(format!("{}-{}.jsonl", name, std::process::id()));
+}
+
+/**
+ * Let the host wait for the client until it moves.
+ */
+fn host_waits(host: &mut RemoteHostGame<PipeAcceptor>) {
+    for _ in 0..MAX_POLLS {
+        if host.wait_move() {
+            return;
+        }
+    }
+    panic!("the client never moved");
+}
+
+/**
+ * Play a short game against a host that records it to `path`.
+ */
+fn record_game(path: &PathBuf) {
+    let recorder = Recorder::create(path).unwrap();
+    let (mut connector, acceptor) = pipe_transport();
+    let acceptor = RecordingAcceptor::new(acceptor, Some(recorder));
+    let (mut host, mut client) = raw_client_of(&mut connector, acceptor,
+                                               Color::Black);
+    let mut reader = JsonReader::default();
+
+    let illegal = ClientToServer::Move(mv(ILLEGAL, true));
+    let s2c = exchange(&mut host, &mut client, &mut reader, &illegal);
+    assert!(matches!(s2c, ServerToClient::Error { .. }));
+
+    alternate(&mut host, &mut client, &mut reader, &MOVES);
+}
+
+#[test]
+fn recordings_hold_every_message() {
+    let path = recording_path("recordings-hold-every-message");
+    record_game(&path);
+    let records = recorder::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(records.iter().all(|record| record.connection == 0));
+    assert!(!recorded_by_client(&records, 0));
+
+    /* handshake, three client moves and four answers */
+    let received = records.iter()
+        .filter(|record| record.direction == Direction::Received)
+        .count();
+    assert_eq!(received, 4);
+    assert_eq!(records.len() - received, 5);
+
+    let handshake = serde_json::to_value(
+        ClientToServerHandshake { server_color: Color::Black }).unwrap();
+    assert_eq!(records[0].message, handshake);
+    assert!(records.iter().all(|record| record.message != Value::Null));
+}
+
+#[test]
+fn recordings_replay_against_a_host() {
+    let path = recording_path("recordings-replay-against-a-host");
+    record_game(&path);
+    let records = recorder::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let (mut connector, acceptor) = pipe_transport();
+    let mut stream = connector.connect().unwrap();
+    let client = thread::spawn(move || {
+        return recorder::replay(&records, 0, &mut stream, REPLAY_TIMEOUT);
+    });
+
+    /* the host plays its part again, the client's comes from the recording */
+    let mut host = RemoteHostGame::new(acceptor).expect("host handshake");
+    host_waits(&mut host);
+    let reply = proto_to_chess_move(&mv(MOVES[1], false)).unwrap();
+    assert!(host.apply_move(&reply));
+    while !client.is_finished() {
+        host.wait_move();
+    }
+
+    assert_eq!(client.join().unwrap().unwrap(), 0);
+}
+
+#[test]
+fn recordings_tell_who_made_each_connection() {
+    let record = |connection, direction| Record {
+        time_ms: 0,
+        connection,
+        direction,
+        message: Value::Null,
+        invalid: None,
+    };
+    let records = [
+        record(0, Direction::Received),
+        record(1, Direction::Sent),
+        record(0, Direction::Sent),
+        record(1, Direction::Received),
+    ];
+
+    assert!(!recorded_by_client(&records, 0));
+    assert!(recorded_by_client(&records, 1));
+    assert!(!recorded_by_client(&records, 2));
+}
@@ -0,0 +1,101 @@
+/*
+ * Run the bundled scripts in scripts/ against our own host and client, so the
+ * examples keep working with this implementation, and a script imitating a
+ * server with fewer features.
+ */
+
+use std::thread;
+
+use chess_network_protocol::{Color, Features};
+use sieric_chess_gui::chess_engine::*;
+use sieric_chess_gui::pipe::*;
+use sieric_chess_gui::remote_engine::RemoteGame;
+use sieric_chess_gui::remote_host_engine::RemoteHostGame;
+use sieric_chess_gui::script::{self, Role, Runner, Step};
+use sieric_chess_gui::transport::*;
+
+const CLIENT_SCRIPT: &str = include_str!("../scripts/client-edge-cases.txt");
+const SERVER_SCRIPT: &str = include_str!("../scripts/server-edge-cases.txt");
+
+#[test]
+fn client_script_passes_against_the_host() {
+    let lines = script::parse(Role::Client, CLIENT_SCRIPT).unwrap();
+    let (mut connector, acceptor) = pipe_transport();
+    let stream = connector.connect().unwrap();
+
+    /* the script sends the handshake the host waits for */
+    let client = thread::spawn(move || {
+        return Runner::new(stream, Role::Client).run(&lines);
+    });
+    let mut host = RemoteHostGame::new(acceptor).expect("host handshake");
+    while !client.is_finished() {
+        host.wait_move();
+    }
+
+    assert_eq!(client.join().unwrap().unwrap(), 0);
+}
+
+#[test]
+fn server_script_passes_against_the_client() {
+    let lines = script::parse(Role::Server, SERVER_SCRIPT).unwrap();
+    let (connector, mut acceptor) = pipe_transport();
+
+    let server = thread::spawn(move || {
+        let stream = acceptor.accept()?;
+        return Runner::new(stream, Role::Server).run(&lines);
+    });
+    let mut game = RemoteGame::new(connector, Color::White)
+        .expect("client handshake");
+
+    /* the script turns the first try down */
+    let e2e4 = ChessMove {
+        from: (4, 1),
+        to: (4, 3),
+        capture: false,
+        promotion: false,
+    };
+    assert!(!game.apply_move(&e2e4));
+    let message = game.take_message().unwrap_or_default();
+    assert!(message.contains("not today"), "{}", message);
+    assert!(game.apply_move(&e2e4));
+    while !server.is_finished() {
+        game.wait_move();
+    }
+
+    assert_eq!(server.join().unwrap().unwrap(), 0);
+}
+
+/// A server that only knows promotion
+const PROMOTION_ONLY: &str = "expect {\"server_color\": \"Black\"}
+handshake promotion
+expect-closed
+";
+
+#[test]
+fn server_scripts_pick_their_features() {
+    let lines = script::parse(Role::Server, "handshake castling promotion")
+        .unwrap();
+    assert!(matches!(&lines[0].step, Step::ServerHandshake(features)
+        if matches!(features[..],
+                    [Features::Castling, Features::Promotion])));
+    let lines = script::parse(Role::Server, "handshake none").unwrap();
+    assert!(matches!(&lines[0].step, Step::ServerHandshake(features)
+        if features.is_empty()));
+    assert!(script::parse(Role::Server, "handshake sideways").is_err());
+
+    let lines = script::parse(Role::Server, PROMOTION_ONLY).unwrap();
+    let (connector, mut acceptor) = pipe_transport();
+    let server = thread::spawn(move || {
+        let stream = acceptor.accept()?;
+        return Runner::new(stream, Role::Server).run(&lines);
+    });
+    let game = RemoteGame::new(connector, Color::White)
+        .expect("client handshake");
+
+    let rules = game.rules();
+    assert!(rules.contains(&String::from("Castling: no")), "{:?}", rules);
+    assert!(rules.contains(&String::from("En passant: no")), "{:?}", rules);
+    assert!(rules.contains(&String::from("Promotion: yes")), "{:?}", rules);
+    drop(game);
+    assert_eq!(server.join().unwrap().unwrap(), 0);
+}